@@ -0,0 +1,167 @@
+//! Compares `bevy_voxel_world`'s default mesher with our greedy chunk mesher on terrain chunks.
+//!
+//! Run with `cargo bench --bench meshing`. The vertex counts of both meshers are printed before
+//! the timings.
+
+use std::{hint::black_box, sync::Arc};
+
+use bevy::prelude::*;
+use bevy_voxel_world::{
+    custom_meshing::{CHUNK_SIZE_I, generate_chunk_mesh_for_shape},
+    prelude::*,
+};
+use block_mesh::ndshape::{RuntimeShape, Shape};
+use criterion::{Criterion, criterion_group, criterion_main};
+use gcd_voxel_game::{
+    light::ChunkLight,
+    meshing::{MeshingOptions, opaque_mesh},
+    overlay::TerrainOverlay,
+    voxel::{BlockMaterial, TerrainWorld},
+};
+
+// Full resolution chunks next to the camera, and the coarsest ones at the horizon.
+const STRIDES: [u8; 3] = [1, 4, 32];
+
+const GREEDY: MeshingOptions = MeshingOptions {
+    ambient_occlusion: true,
+    greedy: true,
+    overlay: TerrainOverlay::Off,
+};
+
+// Occluded corners keep faces from merging, so vertex counts are also shown without it.
+const GREEDY_WITHOUT_AO: MeshingOptions = MeshingOptions {
+    ambient_occlusion: false,
+    greedy: true,
+    overlay: TerrainOverlay::Off,
+};
+
+struct SampleChunk {
+    position: IVec3,
+    data_shape: UVec3,
+    voxels: Arc<[WorldVoxel<BlockMaterial>]>,
+    light: ChunkLight,
+}
+
+// Generates chunks around the origin the same way bevy_voxel_world samples them for a LOD.
+fn sample_chunks(terrain: &TerrainWorld, stride: u8) -> Vec<SampleChunk> {
+    let lookup = terrain.voxel_lookup_delegate();
+    let data_shape = terrain.chunk_data_shape(stride);
+    let shape = RuntimeShape::<u32, 3>::new(data_shape.to_array());
+    let scale = CHUNK_SIZE_I / (data_shape.x as i32 - 2).max(1);
+
+    let mut chunks = Vec::new();
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                let position = IVec3::new(x, y, z);
+                let mut voxel_at = lookup(position, stride, None);
+                let voxels: Arc<[_]> = (0..shape.size())
+                    .map(|i| {
+                        let block = UVec3::from_array(shape.delinearize(i)).as_ivec3() - 1;
+                        voxel_at(block * scale + position * CHUNK_SIZE_I, None)
+                    })
+                    .collect();
+                let light = ChunkLight::compute(&voxels, data_shape, &Default::default());
+                chunks.push(SampleChunk {
+                    position,
+                    data_shape,
+                    voxels,
+                    light,
+                });
+            }
+        }
+    }
+    chunks
+}
+
+// The default mesher with water and ice left out, like our opaque pass.
+fn default_mesh(chunk: &SampleChunk, terrain: &TerrainWorld) -> Mesh {
+    let voxels = chunk
+        .voxels
+        .iter()
+        .map(|voxel| match voxel {
+            WorldVoxel::Solid(material) if material.is_translucent() => WorldVoxel::Air,
+            voxel => *voxel,
+        })
+        .collect();
+    generate_chunk_mesh_for_shape(
+        voxels,
+        chunk.position,
+        chunk.data_shape,
+        chunk.data_shape,
+        terrain.texture_index_mapper(),
+    )
+}
+
+fn greedy_mesh(
+    chunk: &SampleChunk,
+    terrain: &TerrainWorld,
+    options: MeshingOptions,
+) -> Option<Mesh> {
+    opaque_mesh(
+        &chunk.voxels,
+        chunk.data_shape,
+        &chunk.light,
+        &terrain.texture_index_mapper(),
+        options,
+        None,
+    )
+}
+
+fn greedy_vertices(
+    chunks: &[SampleChunk],
+    terrain: &TerrainWorld,
+    options: MeshingOptions,
+) -> usize {
+    chunks
+        .iter()
+        .filter_map(|chunk| greedy_mesh(chunk, terrain, options))
+        .map(|mesh| mesh.count_vertices())
+        .sum()
+}
+
+fn meshing(c: &mut Criterion) {
+    let terrain = TerrainWorld::default();
+
+    println!("stride  default vertices  greedy vertices  ratio  without AO  ratio");
+    let strides: Vec<_> = STRIDES
+        .iter()
+        .map(|&stride| (stride, sample_chunks(&terrain, stride)))
+        .collect();
+    for (stride, chunks) in &strides {
+        let default_vertices: usize = chunks
+            .iter()
+            .map(|chunk| default_mesh(chunk, &terrain).count_vertices())
+            .sum();
+        let ratio = |vertices: usize| vertices as f64 / default_vertices.max(1) as f64;
+        let greedy = greedy_vertices(chunks, &terrain, GREEDY);
+        let without_ao = greedy_vertices(chunks, &terrain, GREEDY_WITHOUT_AO);
+        println!(
+            "{stride:>6}  {default_vertices:>16}  {greedy:>15}  {:>5.2}  {without_ao:>10}  {:>5.2}",
+            ratio(greedy),
+            ratio(without_ao),
+        );
+    }
+
+    for (stride, chunks) in &strides {
+        let mut group = c.benchmark_group(format!("stride {stride}"));
+        group.bench_function("default", |b| {
+            b.iter(|| {
+                for chunk in chunks {
+                    black_box(default_mesh(chunk, &terrain));
+                }
+            })
+        });
+        group.bench_function("greedy", |b| {
+            b.iter(|| {
+                for chunk in chunks {
+                    black_box(greedy_mesh(chunk, &terrain, GREEDY));
+                }
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, meshing);
+criterion_main!(benches);
@@ -0,0 +1,129 @@
+use std::{
+    env,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use image::{GenericImage, RgbaImage};
+
+// Every directory in here is a resource pack, and every PNG in a pack becomes one layer of that
+// pack's voxel texture array. Layers are ordered by file name so the generated index is stable
+// no matter the order the filesystem lists them in.
+const RESOURCE_PACK_DIR: &str = "assets/textures/packs";
+const PACK_MANIFEST: &str = "pack.ron";
+
+fn main() {
+    println!("cargo::rerun-if-changed={RESOURCE_PACK_DIR}");
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    fs::create_dir_all(out_dir.join("packs")).expect("failed to create the packs output dir");
+
+    let mut source = String::new();
+    writeln!(source, "pub static RESOURCE_PACKS: &[GeneratedPack] = &[").unwrap();
+    for pack_dir in sorted_entries(Path::new(RESOURCE_PACK_DIR), |path| path.is_dir()) {
+        let name = file_stem(&pack_dir);
+        let tiles = sorted_entries(&pack_dir, |path| {
+            path.extension().is_some_and(|ext| ext == "png")
+        });
+        assert!(
+            !tiles.is_empty(),
+            "resource pack {} has no block textures",
+            pack_dir.display()
+        );
+
+        let atlas_path = out_dir.join("packs").join(format!("{name}.png"));
+        let (atlas, tile_names) = stack_tiles(&tiles);
+        atlas
+            .save(&atlas_path)
+            .unwrap_or_else(|e| panic!("failed to write {}: {e}", atlas_path.display()));
+
+        writeln!(source, "    GeneratedPack {{").unwrap();
+        writeln!(source, "        name: {name:?},").unwrap();
+        writeln!(source, "        layers: {},", tile_names.len()).unwrap();
+        writeln!(
+            source,
+            "        atlas_png: include_bytes!(concat!(env!(\"OUT_DIR\"), \"/packs/{name}.png\")),"
+        )
+        .unwrap();
+        writeln!(source, "        tiles: &[").unwrap();
+        for (index, tile) in tile_names.iter().enumerate() {
+            writeln!(source, "            ({tile:?}, {index}),").unwrap();
+        }
+        writeln!(source, "        ],").unwrap();
+        let manifest = pack_dir.join(PACK_MANIFEST);
+        if manifest.is_file() {
+            let manifest = fs::canonicalize(&manifest).expect("manifest path resolves");
+            writeln!(
+                source,
+                "        manifest: Some(include_str!({manifest:?})),"
+            )
+            .unwrap();
+        } else {
+            writeln!(source, "        manifest: None,").unwrap();
+        }
+        writeln!(source, "    }},").unwrap();
+    }
+    writeln!(source, "];").unwrap();
+
+    fs::write(out_dir.join("resource_packs.rs"), source)
+        .expect("failed to write resource_packs.rs");
+}
+
+fn sorted_entries(dir: &Path, filter: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("failed to read {}: {e}", dir.display()))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| filter(path))
+        .collect();
+    entries.sort();
+    entries
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_else(|| panic!("{} has no usable file name", path.display()))
+        .to_string()
+}
+
+// The voxel material expects the layers of the array texture stacked vertically in one image,
+// each layer as tall as the image is wide.
+fn stack_tiles(tiles: &[PathBuf]) -> (RgbaImage, Vec<String>) {
+    let mut names = Vec::with_capacity(tiles.len());
+    let mut atlas: Option<RgbaImage> = None;
+
+    for (index, path) in tiles.iter().enumerate() {
+        let tile = image::ImageReader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|e| e.to_string())
+            .and_then(|reader| reader.decode().map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| panic!("failed to decode {}: {e}", path.display()))
+            .into_rgba8();
+        let size = tile.width();
+        assert_eq!(
+            size,
+            tile.height(),
+            "{} must be square, found {}x{}",
+            path.display(),
+            tile.width(),
+            tile.height()
+        );
+
+        let atlas = atlas.get_or_insert_with(|| RgbaImage::new(size, size * tiles.len() as u32));
+        assert_eq!(
+            size,
+            atlas.width(),
+            "{} is {size}px wide but the other textures of its pack are {}px",
+            path.display(),
+            atlas.width()
+        );
+        atlas
+            .copy_from(&tile, 0, size * index as u32)
+            .expect("tile fits inside the atlas");
+
+        names.push(file_stem(path));
+    }
+
+    (atlas.expect("at least one tile"), names)
+}
@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{
+    asset::io::embedded::EmbeddedAssetRegistry, platform::collections::HashMap, prelude::*,
+};
+
+/// A resource pack directory from `assets/textures/packs`, as assembled by build.rs.
+pub struct GeneratedPack {
+    /// Name of the pack directory.
+    pub name: &'static str,
+    /// Number of tiles stacked in `atlas_png`.
+    pub layers: u32,
+    pub atlas_png: &'static [u8],
+    /// Layer index of every tile, keyed by the file stem of its PNG.
+    pub tiles: &'static [(&'static str, u32)],
+    /// Contents of the pack's `pack.ron`, if it has one.
+    pub manifest: Option<&'static str>,
+}
+
+// Generated by build.rs, defines `RESOURCE_PACKS`.
+include!(concat!(env!("OUT_DIR"), "/resource_packs.rs"));
+
+/// Makes the atlas of every generated pack loadable through [`atlas_path`].
+///
+/// Has to be built before anything requests a pack texture from the `AssetServer`.
+pub struct AtlasPlugin;
+
+impl Plugin for AtlasPlugin {
+    fn build(&self, app: &mut App) {
+        let registry = app.world().resource::<EmbeddedAssetRegistry>();
+        for pack in RESOURCE_PACKS {
+            let path = atlas_path(pack.name);
+            let asset_path = path
+                .strip_prefix("embedded://")
+                .expect("atlas path uses the embedded source");
+            registry.insert_asset(PathBuf::new(), Path::new(asset_path), pack.atlas_png);
+        }
+    }
+}
+
+/// Asset path of a pack's atlas, as understood by the `AssetServer`.
+pub fn atlas_path(pack: &str) -> String {
+    format!("embedded://gcd_voxel_game/textures/packs/{pack}.png")
+}
+
+/// Name to layer lookup for the tiles of a voxel atlas.
+#[derive(Debug, Clone)]
+pub struct TextureAtlas {
+    layers: u32,
+    indices: HashMap<String, u32>,
+}
+
+impl TextureAtlas {
+    pub fn generated(pack: &GeneratedPack) -> Self {
+        Self {
+            layers: pack.layers,
+            indices: pack
+                .tiles
+                .iter()
+                .map(|(name, index)| (name.to_string(), *index))
+                .collect(),
+        }
+    }
+
+    /// Number of layers in the array texture.
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
+
+    /// Layer index of the tile built from `<name>.png`, if the atlas has one.
+    pub fn index_of(&self, name: &str) -> Option<u32> {
+        self.indices.get(name).copied()
+    }
+}
@@ -0,0 +1,234 @@
+use std::{f32::consts::PI, fmt::Write as _, fs};
+
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+    window::{PresentMode, PrimaryWindow},
+};
+use bevy_voxel_world::prelude::Chunk;
+use serde::Serialize;
+
+use crate::{
+    AppState,
+    camera_path::{CameraKeyframe, CameraPath, CameraSpline, PathPlayback},
+    fly_controller::SpawnPoint,
+    settings::Settings,
+    voxel::TerrainWorld,
+};
+
+const REPORT_JSON_PATH: &str = "benchmark.json";
+const REPORT_CSV_PATH: &str = "benchmark.csv";
+
+// The route circles the spawn point this far from it and this high above it.
+const ROUTE_RADIUS: f32 = 192.0;
+const ROUTE_HEIGHT: f32 = 48.0;
+const ROUTE_KEYFRAMES: usize = 16;
+const ROUTE_PITCH: f32 = -0.3;
+
+/// Flies the camera along a built-in route for `duration` seconds, then writes a report of the
+/// frame times and chunk spawns to `benchmark.json` and `benchmark.csv` and quits.
+///
+/// The route starts once the assets are loaded and the spawn point is found. Vsync is turned off,
+/// so frame times are not capped by the display.
+pub struct BenchmarkPlugin {
+    pub duration: f32,
+}
+
+impl BenchmarkPlugin {
+    /// How long the benchmark runs when no duration is given, in seconds.
+    pub const DEFAULT_DURATION: f32 = 60.0;
+}
+
+impl Plugin for BenchmarkPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<FrameTimeDiagnosticsPlugin>() {
+            app.add_plugins(FrameTimeDiagnosticsPlugin::default());
+        }
+        app.insert_resource(BenchmarkDuration(self.duration))
+            .add_systems(Startup, disable_vsync)
+            .add_systems(
+                Update,
+                (
+                    // Waits for the spawn point, which the route circles.
+                    start_benchmark.run_if(not(resource_exists::<BenchmarkRun>)),
+                    record_frame.run_if(resource_exists::<BenchmarkRun>),
+                )
+                    .run_if(in_state(AppState::Ready)),
+            );
+    }
+}
+
+#[derive(Resource)]
+struct BenchmarkDuration(f32);
+
+/// The samples of the running benchmark, one per frame. Recording and playing camera paths is
+/// disabled while it exists, so the route can't be interrupted.
+#[derive(Resource, Default)]
+pub struct BenchmarkRun {
+    elapsed: f32,
+    frames: Vec<FrameSample>,
+}
+
+struct FrameSample {
+    time: f32,
+    frame_time_ms: f64,
+    chunks_spawned: usize,
+}
+
+/// The summary written to `benchmark.json`.
+#[derive(Serialize)]
+struct Report<'a> {
+    world_preset: &'a str,
+    resource_pack: &'a str,
+    ambient_occlusion: bool,
+    greedy_meshing: bool,
+    duration_secs: f32,
+    frames: usize,
+    average_fps: f32,
+    frame_time_ms: FrameTimes,
+    chunks_spawned: usize,
+    chunks_spawned_per_sec: f32,
+}
+
+#[derive(Serialize)]
+struct FrameTimes {
+    mean: f64,
+    min: f64,
+    p50: f64,
+    p90: f64,
+    p95: f64,
+    p99: f64,
+    max: f64,
+}
+
+fn disable_vsync(mut window: Single<&mut Window, With<PrimaryWindow>>) {
+    window.present_mode = PresentMode::AutoNoVsync;
+}
+
+fn start_benchmark(
+    mut commands: Commands,
+    duration: Res<BenchmarkDuration>,
+    spawn: Res<SpawnPoint>,
+) {
+    let Some(center) = spawn.position() else {
+        return;
+    };
+    let spline = CameraSpline::new(&route(center, duration.0))
+        .expect("The benchmark route should have keyframes at different times");
+    info!("Running a {:.0}s benchmark", duration.0);
+    commands.insert_resource(PathPlayback {
+        spline,
+        elapsed: 0.0,
+    });
+    commands.init_resource::<BenchmarkRun>();
+}
+
+// One loop around `center`, facing along the way and a bit down at the terrain.
+fn route(center: Vec3, duration: f32) -> CameraPath {
+    let keyframes = (0..=ROUTE_KEYFRAMES)
+        .map(|i| {
+            let progress = i as f32 / ROUTE_KEYFRAMES as f32;
+            let angle = progress * 2.0 * PI;
+            CameraKeyframe {
+                time: progress * duration,
+                position: center
+                    + Vec3::new(
+                        angle.cos() * ROUTE_RADIUS,
+                        ROUTE_HEIGHT,
+                        angle.sin() * ROUTE_RADIUS,
+                    ),
+                // The camera looks down -Z at no yaw, so this faces the direction of travel.
+                yaw: PI - angle,
+                pitch: ROUTE_PITCH,
+            }
+        })
+        .collect();
+    CameraPath { keyframes }
+}
+
+fn record_frame(
+    time: Res<Time>,
+    diagnostics: Res<DiagnosticsStore>,
+    duration: Res<BenchmarkDuration>,
+    settings: Res<Settings>,
+    spawned_chunks: Query<(), Added<Chunk<TerrainWorld>>>,
+    mut run: ResMut<BenchmarkRun>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    run.elapsed += time.delta_secs();
+    let frame_time_ms = diagnostics
+        .get(&FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|frame_time| frame_time.value())
+        .unwrap_or(time.delta_secs_f64() * 1000.0);
+    let sample = FrameSample {
+        time: run.elapsed,
+        frame_time_ms,
+        chunks_spawned: spawned_chunks.iter().count(),
+    };
+    run.frames.push(sample);
+    if run.elapsed < duration.0 {
+        return;
+    }
+    write_report(&run, &settings);
+    exit.write(AppExit::Success);
+}
+
+fn write_report(run: &BenchmarkRun, settings: &Settings) {
+    let mut frame_times: Vec<f64> = run.frames.iter().map(|f| f.frame_time_ms).collect();
+    frame_times.sort_by(f64::total_cmp);
+    // Nearest rank percentile of the sorted frame times.
+    let percentile = |p: f64| {
+        let rank = (p / 100.0 * frame_times.len() as f64).ceil() as usize;
+        frame_times[rank.clamp(1, frame_times.len()) - 1]
+    };
+    let frames = frame_times.len();
+    let mean = frame_times.iter().sum::<f64>() / frames as f64;
+    let chunks_spawned: usize = run.frames.iter().map(|f| f.chunks_spawned).sum();
+
+    let report = Report {
+        world_preset: &settings.world_preset,
+        resource_pack: &settings.resource_pack,
+        ambient_occlusion: settings.ambient_occlusion,
+        greedy_meshing: settings.greedy_meshing,
+        duration_secs: run.elapsed,
+        frames,
+        average_fps: frames as f32 / run.elapsed,
+        frame_time_ms: FrameTimes {
+            mean,
+            min: frame_times[0],
+            p50: percentile(50.0),
+            p90: percentile(90.0),
+            p95: percentile(95.0),
+            p99: percentile(99.0),
+            max: frame_times[frames - 1],
+        },
+        chunks_spawned,
+        chunks_spawned_per_sec: chunks_spawned as f32 / run.elapsed,
+    };
+    let json = match serde_json::to_string_pretty(&report) {
+        Ok(json) => json,
+        Err(e) => {
+            error!("Could not serialize the benchmark report: {e}");
+            return;
+        }
+    };
+    let mut csv = String::from("frame,time_secs,frame_time_ms,chunks_spawned\n");
+    for (frame, sample) in run.frames.iter().enumerate() {
+        let _ = writeln!(
+            csv,
+            "{frame},{:.4},{:.3},{}",
+            sample.time, sample.frame_time_ms, sample.chunks_spawned
+        );
+    }
+
+    for (path, contents) in [(REPORT_JSON_PATH, json), (REPORT_CSV_PATH, csv)] {
+        match fs::write(path, contents) {
+            Ok(()) => info!("Wrote the benchmark report to {path}"),
+            Err(e) => error!("Could not write {path}: {e}"),
+        }
+    }
+    info!(
+        "{frames} frames, {mean:.2} ms mean, {:.2} ms p99, {chunks_spawned} chunks spawned",
+        percentile(99.0)
+    );
+}
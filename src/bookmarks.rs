@@ -0,0 +1,86 @@
+use std::{collections::BTreeMap, fs, io::ErrorKind};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const BOOKMARKS_PATH: &str = "bookmarks.ron";
+
+/// Loads the camera bookmarks on startup and writes them back whenever they change.
+pub struct BookmarksPlugin;
+
+impl Plugin for BookmarksPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Bookmarks::load()).add_systems(
+            Update,
+            save_bookmarks
+                .run_if(resource_changed::<Bookmarks>.and(not(resource_added::<Bookmarks>))),
+        );
+    }
+}
+
+/// A camera pose to return to.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+/// The camera bookmarks of every world, by the name of its preset.
+#[derive(Resource, Default, Clone, Debug, PartialEq)]
+pub struct Bookmarks(BTreeMap<String, Vec<Bookmark>>);
+
+impl Bookmarks {
+    pub fn of_world(&self, preset: &str) -> &[Bookmark] {
+        self.0.get(preset).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn add(&mut self, preset: &str, bookmark: Bookmark) {
+        self.0.entry(preset.to_string()).or_default().push(bookmark);
+    }
+
+    pub fn remove(&mut self, preset: &str, index: usize) {
+        let Some(bookmarks) = self.0.get_mut(preset) else {
+            return;
+        };
+        if index < bookmarks.len() {
+            bookmarks.remove(index);
+        }
+        if bookmarks.is_empty() {
+            self.0.remove(preset);
+        }
+    }
+
+    fn load() -> Self {
+        match fs::read_to_string(BOOKMARKS_PATH) {
+            Ok(contents) => ron::from_str(&contents).map(Bookmarks).unwrap_or_else(|e| {
+                warn!("Ignoring invalid {BOOKMARKS_PATH}: {e}");
+                Bookmarks::default()
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Bookmarks::default(),
+            Err(e) => {
+                warn!("Could not read {BOOKMARKS_PATH}: {e}");
+                Bookmarks::default()
+            }
+        }
+    }
+
+    fn save(&self) {
+        let contents = match ron::ser::to_string_pretty(&self.0, ron::ser::PrettyConfig::default())
+        {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!("Could not serialize bookmarks: {e}");
+                return;
+            }
+        };
+        if let Err(e) = fs::write(BOOKMARKS_PATH, contents) {
+            warn!("Could not write {BOOKMARKS_PATH}: {e}");
+        }
+    }
+}
+
+fn save_bookmarks(bookmarks: Res<Bookmarks>) {
+    bookmarks.save();
+}
@@ -0,0 +1,245 @@
+use std::{fs, io::ErrorKind};
+
+use bevy::prelude::*;
+use bevy_voxel_world::prelude::VoxelWorldCamera;
+use serde::{Deserialize, Serialize};
+use splines::{Interpolation, Key, Spline};
+
+use crate::{
+    benchmark::BenchmarkRun,
+    fly_controller::FlyController,
+    input::{Action, ActionState},
+    voxel::TerrainWorld,
+};
+
+const CAMERA_PATH_PATH: &str = "camera_path.ron";
+
+/// Records camera keyframes while flying and plays them back as a smooth flythrough.
+///
+/// Recording starts with the first keyframe where the camera is, adds one more for every
+/// keyframe action and saves the path when stopped with the last one. While a [`PathPlayback`]
+/// is running it moves the camera instead of the [`FlyController`].
+pub struct CameraPathPlugin;
+
+impl Plugin for CameraPathPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PathRecorder>().add_systems(
+            Update,
+            (
+                // The benchmark route plays without being stopped or recorded over.
+                (record_path, toggle_playback).run_if(not(resource_exists::<BenchmarkRun>)),
+                play_path.run_if(resource_exists::<PathPlayback>),
+            )
+                .chain(),
+        );
+    }
+}
+
+/// A camera pose along a [`CameraPath`], reached `time` seconds after its start.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct CameraKeyframe {
+    pub time: f32,
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+/// Keyframes of a flythrough, sorted by time.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct CameraPath {
+    pub keyframes: Vec<CameraKeyframe>,
+}
+
+impl CameraPath {
+    pub fn load() -> Option<Self> {
+        match fs::read_to_string(CAMERA_PATH_PATH) {
+            Ok(contents) => ron::from_str(&contents)
+                .inspect_err(|e| warn!("Ignoring invalid {CAMERA_PATH_PATH}: {e}"))
+                .ok(),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                warn!("There is no {CAMERA_PATH_PATH} to play, record one first");
+                None
+            }
+            Err(e) => {
+                warn!("Could not read {CAMERA_PATH_PATH}: {e}");
+                None
+            }
+        }
+    }
+
+    fn save(&self) {
+        let contents = match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!("Could not serialize the camera path: {e}");
+                return;
+            }
+        };
+        match fs::write(CAMERA_PATH_PATH, contents) {
+            Ok(()) => info!(
+                "Saved {} keyframes to {CAMERA_PATH_PATH}",
+                self.keyframes.len()
+            ),
+            Err(e) => warn!("Could not write {CAMERA_PATH_PATH}: {e}"),
+        }
+    }
+}
+
+/// The Catmull-Rom splines through the keyframes of a [`CameraPath`], one per coordinate of the
+/// position followed by the yaw and the pitch.
+pub struct CameraSpline {
+    splines: [Spline<f32, f32>; 5],
+    start: f32,
+    end: f32,
+}
+
+impl CameraSpline {
+    /// Needs at least two keyframes at different times.
+    pub fn new(path: &CameraPath) -> Option<Self> {
+        let mut keyframes = path.keyframes.clone();
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        // Segments of no duration can't be interpolated.
+        keyframes.dedup_by(|a, b| a.time == b.time);
+        if keyframes.len() < 2 {
+            return None;
+        }
+        let values = |keyframe: &CameraKeyframe| {
+            let position = keyframe.position;
+            [
+                position.x,
+                position.y,
+                position.z,
+                keyframe.yaw,
+                keyframe.pitch,
+            ]
+        };
+        let splines = std::array::from_fn(|i| {
+            let keys = keyframes
+                .iter()
+                .map(|keyframe| (keyframe.time, values(keyframe)[i]))
+                .collect::<Vec<_>>();
+            // Catmull-Rom needs a key on either side of a segment, so the ends are extended by
+            // mirroring their neighbouring keys.
+            let mirror =
+                |end: (f32, f32), next: (f32, f32)| (2.0 * end.0 - next.0, 2.0 * end.1 - next.1);
+            let first = mirror(keys[0], keys[1]);
+            let last = mirror(keys[keys.len() - 1], keys[keys.len() - 2]);
+            Spline::from_iter(
+                std::iter::once(first)
+                    .chain(keys)
+                    .chain(std::iter::once(last))
+                    .map(|(t, value)| Key::new(t, value, Interpolation::CatmullRom)),
+            )
+        });
+        Some(Self {
+            splines,
+            start: keyframes[0].time,
+            end: keyframes[keyframes.len() - 1].time,
+        })
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.end - self.start
+    }
+
+    /// The camera position, yaw and pitch `elapsed` seconds after the start, held at the ends.
+    pub fn sample(&self, elapsed: f32) -> (Vec3, f32, f32) {
+        // The last key only ends a segment, so sampling stops just before it.
+        let t = (self.start + elapsed).clamp(self.start, self.end - 1e-4);
+        let [x, y, z, yaw, pitch] = self
+            .splines
+            .each_ref()
+            .map(|spline| spline.sample(t).unwrap_or_default());
+        (Vec3::new(x, y, z), yaw, pitch)
+    }
+}
+
+/// The path being recorded, if any, with the time it was started at.
+#[derive(Resource, Default)]
+struct PathRecorder(Option<(f32, CameraPath)>);
+
+/// Moves the camera along a [`CameraSpline`] while present, removing itself at the end.
+#[derive(Resource)]
+pub struct PathPlayback {
+    pub spline: CameraSpline,
+    pub elapsed: f32,
+}
+
+fn record_path(
+    time: Res<Time>,
+    actions: Res<ActionState>,
+    mut recorder: ResMut<PathRecorder>,
+    camera: Single<(&FlyController, &Transform), With<VoxelWorldCamera<TerrainWorld>>>,
+) {
+    let toggle = actions.just_pressed(Action::RecordPath);
+    // Keyframes are only added to a path already being recorded.
+    let add = actions.just_pressed(Action::AddKeyframe) && recorder.0.is_some();
+    if !toggle && !add {
+        return;
+    }
+    let now = time.elapsed_secs();
+    let (controller, transform) = *camera;
+    let (start, path) = recorder.0.get_or_insert_with(|| {
+        info!("Recording a camera path");
+        (now, CameraPath::default())
+    });
+    path.keyframes.push(CameraKeyframe {
+        time: now - *start,
+        position: transform.translation,
+        yaw: controller.yaw(),
+        pitch: controller.pitch(),
+    });
+    if toggle && path.keyframes.len() > 1 {
+        path.save();
+        recorder.0 = None;
+    }
+}
+
+// Starts playing the saved path, or stops the one playing.
+fn toggle_playback(
+    mut commands: Commands,
+    actions: Res<ActionState>,
+    playback: Option<Res<PathPlayback>>,
+) {
+    if !actions.just_pressed(Action::PlayPath) {
+        return;
+    }
+    if playback.is_some() {
+        info!("Stopped playing the camera path");
+        commands.remove_resource::<PathPlayback>();
+        return;
+    }
+    let Some(path) = CameraPath::load() else {
+        return;
+    };
+    match CameraSpline::new(&path) {
+        Some(spline) => {
+            info!("Playing a {:.1}s camera path", spline.duration());
+            commands.insert_resource(PathPlayback {
+                spline,
+                elapsed: 0.0,
+            });
+        }
+        None => warn!("{CAMERA_PATH_PATH} needs at least two keyframes"),
+    }
+}
+
+fn play_path(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut playback: ResMut<PathPlayback>,
+    mut camera: Single<(&mut FlyController, &mut Transform), With<VoxelWorldCamera<TerrainWorld>>>,
+) {
+    let (controller, transform) = &mut *camera;
+    playback.elapsed += time.delta_secs();
+    let (position, yaw, pitch) = playback.spline.sample(playback.elapsed);
+    transform.translation = position;
+    transform.rotation =
+        Quat::from_axis_angle(Vec3::Y, yaw) * Quat::from_axis_angle(Vec3::X, pitch);
+    // The controller carries on from where the path leaves the camera.
+    controller.set_orientation(yaw, pitch);
+    if playback.elapsed >= playback.spline.duration() {
+        info!("Finished playing the camera path");
+        commands.remove_resource::<PathPlayback>();
+    }
+}
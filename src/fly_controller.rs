@@ -6,30 +6,101 @@ use bevy::{
     pbr::{Atmosphere, AtmosphereMode, AtmosphereSettings},
     post_process::bloom::Bloom,
     prelude::*,
+    tasks::{AsyncComputeTaskPool, Task, futures_lite::future},
     window::{CursorGrabMode, CursorOptions, PrimaryWindow},
 };
 
-use bevy_voxel_world::{custom_meshing::CHUNK_SIZE_F, prelude::VoxelWorldCamera};
+use bevy_voxel_world::{
+    custom_meshing::{CHUNK_SIZE_F, CHUNK_SIZE_I},
+    prelude::{VoxelWorld, VoxelWorldCamera, WorldVoxel},
+};
 
-use crate::voxel::TerrainWorld;
+use crate::{
+    camera_path::PathPlayback,
+    input::{Action, ActionState},
+    settings::Settings,
+    voxel::{Biome, BlockMaterial, RegenerateWorld, TerrainWorld, regenerate_world},
+};
 
 pub struct FlyControllerPlugin;
 
 impl Plugin for FlyControllerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup).add_systems(
-            Update,
-            (mouse_capture, camera_look, camera_move, camera_speed),
-        );
+        app.add_message::<Teleport>()
+            // The camera is moved to the spawn point once it is found.
+            .insert_resource(SpawnPoint {
+                respawn: true,
+                ..default()
+            })
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    search_spawn_point.after(regenerate_world),
+                    reach_spawn_point,
+                    teleport,
+                    wait_for_chunks,
+                    mouse_capture,
+                    // A camera path playing moves the camera instead.
+                    (
+                        camera_look,
+                        toggle_movement_mode,
+                        camera_move,
+                        walk_move,
+                        camera_speed,
+                        respawn,
+                    )
+                        .run_if(not(resource_exists::<PathPlayback>)),
+                ),
+            );
+    }
+}
+
+/// Moves the camera to a pose, keeping it still until the chunks around it are generated.
+#[derive(Message, Debug, Clone, Copy)]
+pub struct Teleport {
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+/// The spawn point of the current world. It is searched for on a background task when the world
+/// is generated again, as [`TerrainWorld::find_spawn`] samples many columns.
+#[derive(Resource, Default)]
+pub struct SpawnPoint {
+    position: Option<Vec3>,
+    search: Option<Task<Vec3>>,
+    // Set when the camera should be moved there as soon as the search finishes.
+    respawn: bool,
+}
+
+impl SpawnPoint {
+    /// Where the camera spawns, or `None` while it is still being searched for.
+    pub fn position(&self) -> Option<Vec3> {
+        self.position
     }
 }
 
+/// How the camera moves: flying through everything, or walking on the voxels with gravity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MovementMode {
+    #[default]
+    Fly,
+    Walk,
+}
+
 #[derive(Component)]
 pub struct FlyController {
     speed: f32,
     captured: bool,
     yaw: f32,
     pitch: f32,
+    mode: MovementMode,
+    // Only used while walking.
+    velocity: Vec3,
+    grounded: bool,
+    // Set after teleporting, until the chunks at the destination are generated or it runs out.
+    frozen: Option<Timer>,
 }
 
 impl FlyController {
@@ -38,6 +109,43 @@ impl FlyController {
     const SPEED_MAX: f32 = 100.0;
     const SPEED_STEP: f32 = 2.0;
     const SPEED_INITIAL: f32 = 10.0;
+
+    // The walking body is a box around the camera, standing on its bottom face.
+    const HALF_WIDTH: f32 = 0.3;
+    const BODY_HEIGHT: f32 = 1.8;
+    const EYE_HEIGHT: f32 = 1.62;
+    // Blocks per second (squared).
+    const WALK_SPEED: f32 = 4.3;
+    const SPRINT_SPEED: f32 = 5.6;
+    const JUMP_SPEED: f32 = 8.5;
+    const GRAVITY: f32 = 28.0;
+    const TERMINAL_SPEED: f32 = 60.0;
+    // Obstacles up to this height are stepped onto instead of blocking the way.
+    const STEP_HEIGHT: f32 = 1.05;
+
+    // How long a teleport waits for the chunks at its destination, in seconds.
+    const TELEPORT_TIMEOUT: f32 = 10.0;
+
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    pub fn set_orientation(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = yaw;
+        self.pitch = pitch;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.is_some()
+    }
+
+    pub fn is_captured(&self) -> bool {
+        self.captured
+    }
 }
 
 impl Default for FlyController {
@@ -47,14 +155,35 @@ impl Default for FlyController {
             captured: false,
             yaw: 0.0,
             pitch: 0.0,
+            mode: MovementMode::Fly,
+            velocity: Vec3::ZERO,
+            grounded: false,
+            frozen: None,
         }
     }
 }
 
+// The camera spawns above the ground of the first of these biomes found near the origin.
+const SPAWN_BIOMES: [Biome; 4] = [
+    Biome::Grassland,
+    Biome::Forest,
+    Biome::Savanna,
+    Biome::Beach,
+];
+// Where the camera waits for the spawn point, and stays when there is no ground to spawn on.
+const FALLBACK_SPAWN: Vec3 = Vec3::new(0.0, 64.0, 0.0);
+
+// Where to put the camera to stand on the ground found by TerrainWorld::find_spawn around
+// `center`.
+fn spawn_position(terrain: &TerrainWorld, center: IVec2) -> Option<Vec3> {
+    let ground = terrain.find_spawn(center, &SPAWN_BIOMES)?;
+    Some(ground.as_vec3() + Vec3::new(0.5, 1.0 + FlyController::EYE_HEIGHT, 0.5))
+}
+
 fn setup(mut commands: Commands) {
     commands.spawn((
         Camera3d::default(),
-        Transform::from_xyz(0.0, 64.0, 0.0),
+        Transform::from_translation(FALLBACK_SPAWN),
         VoxelWorldCamera::<TerrainWorld>::default(),
         FlyController::default(),
         Msaa::Sample4,
@@ -90,21 +219,25 @@ fn setup(mut commands: Commands) {
 }
 
 fn mouse_capture(
-    mouse_button_input: Res<ButtonInput<MouseButton>>,
-    key_input: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
     mut primary_cursor_options: Single<&mut CursorOptions, With<PrimaryWindow>>,
     mut query: Query<&mut FlyController>,
+    interactions: Query<&Interaction>,
 ) {
     let mut camera = query
         .single_mut()
         .expect("A FlyController component should be present before mouse_capture_system is run");
 
-    if mouse_button_input.just_pressed(MouseButton::Left) && !camera.captured {
+    // Clicks on buttons and other interactive UI keep the cursor free.
+    let over_ui = interactions
+        .iter()
+        .any(|interaction| *interaction != Interaction::None);
+    if actions.just_pressed(Action::CaptureCursor) && !camera.captured && !over_ui {
         primary_cursor_options.visible = false;
         primary_cursor_options.grab_mode = CursorGrabMode::Locked;
         camera.captured = true;
     }
-    if key_input.just_pressed(KeyCode::Escape) && camera.captured {
+    if actions.just_pressed(Action::ReleaseCursor) && camera.captured {
         primary_cursor_options.visible = true;
         primary_cursor_options.grab_mode = CursorGrabMode::None;
         camera.captured = false;
@@ -112,22 +245,33 @@ fn mouse_capture(
 }
 
 fn camera_look(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    actions: Res<ActionState>,
     mut query: Query<(&mut FlyController, &mut Transform), With<VoxelWorldCamera<TerrainWorld>>>,
     mut mouse_motion_events: MessageReader<MouseMotion>,
 ) {
     let (mut camera, mut transform) = query
         .single_mut()
         .expect("A FlyController component should be present before camera_look_system is run");
-    if !camera.captured {
+    if camera.is_frozen() {
         return;
     }
     let mut delta = Vec2::ZERO;
-    for event in mouse_motion_events.read() {
-        delta += event.delta;
+    if camera.captured {
+        for event in mouse_motion_events.read() {
+            delta += event.delta * FlyController::MOUSE_SENSITIVITY * 0.01;
+        }
     }
+    // Looking up turns the other way than moving the mouse up, which has a negative delta.
+    let look = Vec2::new(
+        actions.axis(Action::LookLeft, Action::LookRight),
+        actions.axis(Action::LookUp, Action::LookDown),
+    );
+    delta += look * settings.gamepad_look_sensitivity * time.delta_secs();
     if delta != Vec2::ZERO {
-        camera.yaw -= delta.x * FlyController::MOUSE_SENSITIVITY * 0.01;
-        camera.pitch -= delta.y * FlyController::MOUSE_SENSITIVITY * 0.01;
+        camera.yaw -= delta.x;
+        camera.pitch -= delta.y;
         camera.pitch = camera.pitch.clamp(-1.54, 1.54); // ~89 deg
         let rot = Quat::from_axis_angle(Vec3::Y, camera.yaw)
             * Quat::from_axis_angle(Vec3::X, camera.pitch);
@@ -137,48 +281,392 @@ fn camera_look(
 
 fn camera_move(
     time: Res<Time>,
-    key_input: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
     mut query: Query<(&FlyController, &mut Transform), With<VoxelWorldCamera<TerrainWorld>>>,
 ) {
     let (camera, mut transform) = query
         .single_mut()
         .expect("A FlyController component should be present before camera_move_system is run");
-    if !camera.captured {
+    if camera.mode != MovementMode::Fly || camera.is_frozen() {
         return;
     }
-    let mut direction = Vec3::ZERO;
-    if key_input.pressed(KeyCode::KeyW) {
-        direction += *transform.forward();
+    // Analog inputs move as fast as they are pushed.
+    let direction = (*transform.forward() * actions.axis(Action::MoveBack, Action::MoveForward)
+        + *transform.right() * actions.axis(Action::MoveLeft, Action::MoveRight)
+        + *transform.up() * actions.axis(Action::MoveDown, Action::MoveUp))
+    .clamp_length_max(1.0);
+    if direction != Vec3::ZERO {
+        transform.translation += direction * camera.speed * time.delta_secs();
     }
-    if key_input.pressed(KeyCode::KeyS) {
-        direction -= *transform.forward();
+}
+
+fn toggle_movement_mode(actions: Res<ActionState>, mut camera: Single<&mut FlyController>) {
+    if actions.just_pressed(Action::ToggleMovementMode) {
+        camera.mode = match camera.mode {
+            MovementMode::Fly => MovementMode::Walk,
+            MovementMode::Walk => MovementMode::Fly,
+        };
+        camera.velocity = Vec3::ZERO;
+        camera.grounded = false;
+        info!("Movement mode: {:?}", camera.mode);
     }
-    if key_input.pressed(KeyCode::KeyA) {
-        direction -= *transform.right();
+}
+
+fn walk_move(
+    time: Res<Time>,
+    actions: Res<ActionState>,
+    voxel_world: VoxelWorld<TerrainWorld>,
+    mut query: Query<(&mut FlyController, &mut Transform), With<VoxelWorldCamera<TerrainWorld>>>,
+) {
+    let (mut camera, mut transform) = query
+        .single_mut()
+        .expect("A FlyController component should be present before walk_move is run");
+    if camera.mode != MovementMode::Walk || camera.is_frozen() {
+        return;
     }
-    if key_input.pressed(KeyCode::KeyD) {
-        direction += *transform.right();
+    // Long frames would let the body fall through thin floors in one step.
+    let delta = time.delta_secs().min(0.05);
+    let get_voxel = voxel_world.get_voxel_fn();
+    let solid = |position: IVec3| blocks_walking(get_voxel(position));
+
+    if camera.grounded && actions.pressed(Action::Jump) {
+        camera.velocity.y = FlyController::JUMP_SPEED;
     }
-    if key_input.pressed(KeyCode::Space) {
-        direction += *transform.up();
+    let speed = if actions.pressed(Action::Sprint) {
+        FlyController::SPRINT_SPEED
+    } else {
+        FlyController::WALK_SPEED
+    };
+    let forward = transform.forward().with_y(0.0).normalize_or_zero();
+    let right = transform.right().with_y(0.0).normalize_or_zero();
+    let direction = (forward * actions.axis(Action::MoveBack, Action::MoveForward)
+        + right * actions.axis(Action::MoveLeft, Action::MoveRight))
+    .clamp_length_max(1.0);
+    let horizontal = direction * speed;
+    camera.velocity.x = horizontal.x;
+    camera.velocity.z = horizontal.z;
+    camera.velocity.y =
+        (camera.velocity.y - FlyController::GRAVITY * delta).max(-FlyController::TERMINAL_SPEED);
+    let motion = camera.velocity * delta;
+
+    let mut feet = transform.translation - Vec3::Y * FlyController::EYE_HEIGHT;
+    let fallen = move_body(&mut feet, 1, motion.y, &solid);
+    let landed = fallen.abs() < motion.y.abs();
+    camera.grounded = landed && motion.y < 0.0;
+    if landed {
+        camera.velocity.y = 0.0;
     }
-    if key_input.pressed(KeyCode::ShiftLeft) {
-        direction -= *transform.up();
+
+    walk_body(&mut feet, motion.xz(), camera.grounded, &solid);
+    transform.translation = feet + Vec3::Y * FlyController::EYE_HEIGHT;
+}
+
+// Moves the walking body standing on `feet` horizontally by `motion`, stepping onto obstacles
+// up to the step height when it stands on the ground.
+fn walk_body(feet: &mut Vec3, motion: Vec2, grounded: bool, solid: &impl Fn(IVec3) -> bool) {
+    let start = *feet;
+    move_body(feet, 0, motion.x, solid);
+    move_body(feet, 2, motion.y, solid);
+    let blocked = feet.xz() != start.xz() + motion;
+    if grounded && blocked {
+        // Try the same move from a block higher, then settle back down on whatever is there.
+        let mut stepped = start;
+        let step = FlyController::STEP_HEIGHT;
+        if move_body(&mut stepped, 1, step, solid) == step {
+            move_body(&mut stepped, 0, motion.x, solid);
+            move_body(&mut stepped, 2, motion.y, solid);
+            move_body(&mut stepped, 1, -step, solid);
+            if stepped.xz().distance_squared(start.xz()) > feet.xz().distance_squared(start.xz()) {
+                *feet = stepped;
+            }
+        }
     }
-    if direction != Vec3::ZERO {
-        transform.translation += direction.normalize() * camera.speed * time.delta_secs();
+}
+
+// Unloaded voxels block the body too, so it waits for the chunks around it instead of falling
+// through the world.
+fn blocks_walking(voxel: WorldVoxel<BlockMaterial>) -> bool {
+    match voxel {
+        WorldVoxel::Solid(material) => {
+            !matches!(material, BlockMaterial::Water | BlockMaterial::Lava)
+        }
+        WorldVoxel::Unset => true,
+        WorldVoxel::Air => false,
     }
 }
 
+// Moves the walking body standing on `feet` along one axis until it touches a solid voxel,
+// returning how far it got. Voxels the body already overlaps don't stop it.
+fn move_body(feet: &mut Vec3, axis: usize, distance: f32, solid: &impl Fn(IVec3) -> bool) -> f32 {
+    // Gap kept between the body and the voxels it touches, so it isn't inside them.
+    const SKIN: f32 = 0.001;
+    if distance == 0.0 {
+        return 0.0;
+    }
+    let half_width = FlyController::HALF_WIDTH;
+    let min = *feet - Vec3::new(half_width, 0.0, half_width);
+    let max = *feet + Vec3::new(half_width, FlyController::BODY_HEIGHT, half_width);
+    let covered_min = (min + SKIN).floor().as_ivec3();
+    let covered_max = (max - SKIN).floor().as_ivec3();
+
+    let leading = if distance > 0.0 { max[axis] } else { min[axis] };
+    let target = leading + distance;
+    // The layers of voxels the leading face of the body sweeps through, nearest first.
+    let layers: Box<dyn Iterator<Item = i32>> = if distance > 0.0 {
+        Box::new(covered_max[axis] + 1..=target.floor() as i32)
+    } else {
+        Box::new((target.floor() as i32..covered_min[axis]).rev())
+    };
+    let (a, b) = match axis {
+        0 => (1, 2),
+        1 => (0, 2),
+        _ => (0, 1),
+    };
+
+    let mut moved = distance;
+    'layers: for layer in layers {
+        for i in covered_min[a]..=covered_max[a] {
+            for j in covered_min[b]..=covered_max[b] {
+                let mut voxel = IVec3::ZERO;
+                voxel[axis] = layer;
+                voxel[a] = i;
+                voxel[b] = j;
+                if solid(voxel) {
+                    moved = if distance > 0.0 {
+                        (layer as f32 - SKIN - leading).max(0.0)
+                    } else {
+                        (layer as f32 + 1.0 + SKIN - leading).min(0.0)
+                    };
+                    break 'layers;
+                }
+            }
+        }
+    }
+    feet[axis] += moved;
+    moved
+}
+
+// The mouse wheel changes the flying speed, as do the speed actions a step at a time.
 fn camera_speed(
+    actions: Res<ActionState>,
     mut query: Query<&mut FlyController>,
     mut mouse_wheel_events: MessageReader<MouseWheel>,
 ) {
     let mut camera = query
         .single_mut()
         .expect("A FlyController component should be present before camera_speed_system is run");
-    for event in mouse_wheel_events.read() {
-        camera.speed = (camera.speed + event.y * FlyController::SPEED_STEP)
+    let mut steps: f32 = mouse_wheel_events.read().map(|event| event.y).sum();
+    if actions.just_pressed(Action::SpeedUp) {
+        steps += 1.0;
+    }
+    if actions.just_pressed(Action::SpeedDown) {
+        steps -= 1.0;
+    }
+    if steps != 0.0 {
+        camera.speed = (camera.speed + steps * FlyController::SPEED_STEP)
             .clamp(FlyController::SPEED_MIN, FlyController::SPEED_MAX);
     }
 }
+
+// Puts the camera back at the spawn point of the world, or once it is found.
+fn respawn(
+    actions: Res<ActionState>,
+    mut spawn: ResMut<SpawnPoint>,
+    mut camera: Single<(&mut FlyController, &mut Transform)>,
+) {
+    if !actions.just_pressed(Action::Respawn) {
+        return;
+    }
+    let Some(position) = spawn.position else {
+        spawn.respawn = true;
+        return;
+    };
+    let (controller, transform) = &mut *camera;
+    transform.translation = position;
+    controller.velocity = Vec3::ZERO;
+}
+
+// Starts searching for the spawn point when the world is generated again, and on startup.
+fn search_spawn_point(
+    mut regenerated: MessageReader<RegenerateWorld>,
+    terrain: Res<TerrainWorld>,
+    mut spawn: ResMut<SpawnPoint>,
+) {
+    let searched = spawn.position.is_some() || spawn.search.is_some();
+    if regenerated.read().count() == 0 && searched {
+        return;
+    }
+    let terrain = terrain.clone();
+    spawn.position = None;
+    // Replacing a search that is still running cancels it.
+    spawn.search = Some(
+        AsyncComputeTaskPool::get()
+            .spawn(async move { spawn_position(&terrain, IVec2::ZERO).unwrap_or(FALLBACK_SPAWN) }),
+    );
+}
+
+fn reach_spawn_point(
+    mut spawn: ResMut<SpawnPoint>,
+    mut teleport: MessageWriter<Teleport>,
+    camera: Single<&FlyController>,
+) {
+    let Some(search) = spawn.search.as_mut() else {
+        return;
+    };
+    let Some(position) = future::block_on(future::poll_once(search)) else {
+        return;
+    };
+    info!("Spawn point at {position}");
+    spawn.search = None;
+    spawn.position = Some(position);
+    if spawn.respawn {
+        spawn.respawn = false;
+        teleport.write(Teleport {
+            position,
+            yaw: camera.yaw,
+            pitch: camera.pitch,
+        });
+    }
+}
+
+fn teleport(
+    mut teleports: MessageReader<Teleport>,
+    mut camera: Single<(&mut FlyController, &mut Transform)>,
+) {
+    let Some(destination) = teleports.read().last() else {
+        return;
+    };
+    let (controller, transform) = &mut *camera;
+    controller.yaw = destination.yaw;
+    controller.pitch = destination.pitch;
+    controller.velocity = Vec3::ZERO;
+    controller.grounded = false;
+    controller.frozen = Some(Timer::from_seconds(
+        FlyController::TELEPORT_TIMEOUT,
+        TimerMode::Once,
+    ));
+    transform.translation = destination.position;
+    transform.rotation = Quat::from_axis_angle(Vec3::Y, destination.yaw)
+        * Quat::from_axis_angle(Vec3::X, destination.pitch);
+    info!("Teleporting to {}", destination.position);
+}
+
+// Unfreezes the camera once the chunk it is in and the one below it are generated, so it doesn't
+// fly into or fall through the terrain that is still missing. Other chunks around it may never
+// spawn, as bevy_voxel_world only spawns the ones in view beyond its protected radius.
+fn wait_for_chunks(
+    time: Res<Time>,
+    voxel_world: VoxelWorld<TerrainWorld>,
+    mut camera: Single<(&mut FlyController, &Transform)>,
+) {
+    let (controller, transform) = &mut *camera;
+    let Some(timer) = controller.frozen.as_mut() else {
+        return;
+    };
+    // Rounded towards zero like bevy_voxel_world does to find the chunk at the camera.
+    let center = transform.translation.as_ivec3() / CHUNK_SIZE_I;
+    let generated = [center, center - IVec3::Y].into_iter().all(|position| {
+        voxel_world
+            .get_chunk_data(position)
+            .is_some_and(|chunk| chunk.has_generated())
+    });
+    if generated {
+        info!("Chunks at the destination are ready");
+        controller.frozen = None;
+    } else if timer.tick(time.delta()).is_finished() {
+        warn!("Gave up waiting for the chunks at the destination");
+        controller.frozen = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::world_params::WorldParams;
+
+    const EPSILON: f32 = 1e-4;
+
+    // Ground below y = 0 everywhere.
+    fn ground(voxel: IVec3) -> bool {
+        voxel.y < 0
+    }
+
+    #[test]
+    fn falling_body_lands_on_the_floor() {
+        let mut feet = Vec3::new(0.5, 1.5, 0.5);
+        let moved = move_body(&mut feet, 1, -3.0, &ground);
+        assert!((feet.y - 0.001).abs() < EPSILON, "feet at {}", feet.y);
+        assert!((moved + 1.499).abs() < EPSILON);
+    }
+
+    #[test]
+    fn wall_stops_the_body_a_skin_away() {
+        let wall = |voxel: IVec3| voxel.x >= 2;
+        let mut feet = Vec3::new(1.0, 0.001, 0.5);
+        let moved = move_body(&mut feet, 0, 1.0, &wall);
+        // The leading face stops just short of the wall at x = 2.
+        assert!((feet.x + FlyController::HALF_WIDTH - 1.999).abs() < EPSILON);
+        assert!(moved < 1.0);
+    }
+
+    #[test]
+    fn walking_steps_up_one_block() {
+        let ledge = |voxel: IVec3| ground(voxel) || (voxel.x >= 1 && voxel.y == 0);
+        let mut feet = Vec3::new(0.5, 0.001, 0.5);
+        walk_body(&mut feet, Vec2::new(0.5, 0.0), true, &ledge);
+        assert!((feet.x - 1.0).abs() < EPSILON);
+        assert!((feet.y - 1.001).abs() < EPSILON, "feet at {}", feet.y);
+    }
+
+    #[test]
+    fn walking_does_not_step_up_two_blocks() {
+        let wall = |voxel: IVec3| ground(voxel) || (voxel.x >= 1 && voxel.y <= 1);
+        let mut feet = Vec3::new(0.5, 0.001, 0.5);
+        walk_body(&mut feet, Vec2::new(0.5, 0.0), true, &wall);
+        assert!((feet.x + FlyController::HALF_WIDTH - 0.999).abs() < EPSILON);
+        assert!((feet.y - 0.001).abs() < EPSILON, "feet at {}", feet.y);
+    }
+
+    #[test]
+    fn spawn_point_is_searched_again_only_after_regenerating() {
+        let params = WorldParams::load_preset("superflat").unwrap();
+        let mut app = App::new();
+        app.add_plugins(TaskPoolPlugin::default())
+            .add_message::<Teleport>()
+            .add_message::<RegenerateWorld>()
+            .insert_resource(TerrainWorld::new(&params))
+            .insert_resource(SpawnPoint {
+                respawn: true,
+                ..default()
+            })
+            .add_systems(Update, (search_spawn_point, reach_spawn_point).chain());
+        app.world_mut().spawn(FlyController::default());
+
+        // Standing on the grass at y = -1.
+        let expected = Vec3::new(0.5, FlyController::EYE_HEIGHT, 0.5);
+        let found = searched_spawn_point(&mut app);
+        assert!(found.distance(expected) < EPSILON, "spawned at {found}");
+        assert_eq!(app.world().resource::<Messages<Teleport>>().len(), 1);
+
+        // Caves only worlds have no ground to spawn on.
+        let caves = WorldParams::load_preset("caves_only").unwrap();
+        app.insert_resource(TerrainWorld::new(&caves));
+        app.update();
+        assert_eq!(app.world().resource::<SpawnPoint>().position(), Some(found));
+        app.world_mut().write_message(RegenerateWorld);
+        assert_eq!(searched_spawn_point(&mut app), FALLBACK_SPAWN);
+    }
+
+    fn searched_spawn_point(app: &mut App) -> Vec3 {
+        for _ in 0..100 {
+            app.update();
+            if let Some(position) = app.world().resource::<SpawnPoint>().position() {
+                return position;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("The spawn point search didn't finish");
+    }
+}
@@ -0,0 +1,270 @@
+use std::sync::{Arc, RwLock};
+
+use bevy::{math::DVec2, platform::collections::HashMap, prelude::*};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::world_params::HydraulicErosionParams;
+
+// Past this many regions, the half farthest from the one asked for are dropped.
+const MAX_CACHED_REGIONS: usize = 1024;
+
+/// Droplet erosion run on the terrain heightfield one region at a time.
+///
+/// Each region simulates a heightfield twice its size, centered on it, and remembers how much the
+/// droplets lowered or raised every point. The height change of a column is blended from the four
+/// regions around it, so neighbouring simulations fade into each other without seams.
+pub struct HydraulicErosion {
+    params: HydraulicErosionParams,
+    regions: RwLock<HashMap<IVec2, Arc<RegionErosion>>>,
+}
+
+// Height change at the grid points of a region's heightfield.
+struct RegionErosion {
+    origin: DVec2,
+    cell_size: f64,
+    size: usize,
+    delta: Box<[f32]>,
+}
+
+impl HydraulicErosion {
+    pub fn new(params: HydraulicErosionParams) -> Self {
+        Self {
+            params,
+            regions: default(),
+        }
+    }
+
+    /// How much erosion raises or lowers the terrain at `x`, `z`, given the uneroded height of
+    /// any column.
+    pub fn height_change(&self, x: f64, z: f64, height: impl Fn(f64, f64) -> f64) -> f64 {
+        let region_size = self.params.region_size.max(1) as f64;
+        // Position between the centers of the four nearest regions.
+        let between = DVec2::new(x, z) / region_size - 0.5;
+        let first = between.floor();
+        let t = between - first;
+
+        let mut change = 0.0;
+        for (corner, weight) in [
+            (IVec2::ZERO, (1.0 - t.x) * (1.0 - t.y)),
+            (IVec2::X, t.x * (1.0 - t.y)),
+            (IVec2::Y, (1.0 - t.x) * t.y),
+            (IVec2::ONE, t.x * t.y),
+        ] {
+            if weight > 0.0 {
+                let region = self.region(first.as_ivec2() + corner, &height);
+                change += region.sample(x, z) * weight;
+            }
+        }
+        change
+    }
+
+    fn region(&self, position: IVec2, height: &impl Fn(f64, f64) -> f64) -> Arc<RegionErosion> {
+        if let Some(region) = self.regions.read().unwrap().get(&position) {
+            return Arc::clone(region);
+        }
+        // Simulated outside the lock, another thread may compute the same region meanwhile.
+        let region = Arc::new(self.simulate(position, height));
+        let mut regions = self.regions.write().unwrap();
+        if regions.len() >= MAX_CACHED_REGIONS {
+            // Chunks are generated around the camera, so the regions near this one are the ones
+            // asked for next.
+            let mut by_distance: Vec<IVec2> = regions.keys().copied().collect();
+            by_distance.sort_by_key(|other| other.distance_squared(position));
+            for other in &by_distance[MAX_CACHED_REGIONS / 2..] {
+                regions.remove(other);
+            }
+        }
+        Arc::clone(regions.entry(position).or_insert(region))
+    }
+
+    fn simulate(&self, position: IVec2, height: &impl Fn(f64, f64) -> f64) -> RegionErosion {
+        let params = &self.params;
+        let region_size = params.region_size.max(1) as f64;
+        let cell_size = params.cell_size.max(1) as f64;
+        let origin = (position.as_dvec2() - 0.5) * region_size;
+        // Droplets start inside a cell, so the heightfield needs at least one.
+        let size = ((2.0 * region_size / cell_size) as usize + 1).max(2);
+
+        let original: Vec<f64> = (0..size * size)
+            .map(|index| {
+                let point = DVec2::new((index % size) as f64, (index / size) as f64);
+                let world = origin + point * cell_size;
+                height(world.x, world.y)
+            })
+            .collect();
+        let mut heights = original.clone();
+
+        let seed = (params.seed as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            ^ (position.x as u32 as u64) << 32
+            ^ position.y as u32 as u64;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let droplets = ((size * size) as f64 * params.droplets_per_point) as usize;
+        let last = (size - 1) as f64;
+        for _ in 0..droplets {
+            let start = DVec2::new(rng.random_range(0.0..last), rng.random_range(0.0..last));
+            self.run_droplet(&mut heights, size, start);
+        }
+
+        let delta = heights
+            .iter()
+            .zip(&original)
+            .map(|(eroded, original)| (eroded - original) as f32)
+            .collect();
+        RegionErosion {
+            origin,
+            cell_size,
+            size,
+            delta,
+        }
+    }
+
+    // Moves a droplet downhill, picking up sediment while it is fast and has capacity left and
+    // dropping it where it slows down, flows uphill or stops.
+    fn run_droplet(&self, heights: &mut [f64], size: usize, start: DVec2) {
+        let params = &self.params;
+        let last = (size - 1) as f64;
+        let mut position = start;
+        let mut direction = DVec2::ZERO;
+        let mut speed = 1.0;
+        let mut water = 1.0;
+        let mut sediment = 0.0;
+
+        for _ in 0..params.max_steps {
+            let (height, gradient) = height_and_gradient(heights, size, position);
+            direction = direction * params.inertia - gradient * (1.0 - params.inertia);
+            let Some(step) = direction.try_normalize() else {
+                break;
+            };
+            direction = step;
+            let previous = position;
+            position += direction;
+            if position.cmplt(DVec2::ZERO).any() || position.cmpge(DVec2::splat(last)).any() {
+                position = previous;
+                break;
+            }
+
+            let height_difference = height_and_gradient(heights, size, position).0 - height;
+            let capacity =
+                (-height_difference).max(params.min_slope) * speed * water * params.capacity;
+            if sediment > capacity || height_difference > 0.0 {
+                // Fill the pit the droplet climbs out of, or drop what it can't carry.
+                let deposit = if height_difference > 0.0 {
+                    height_difference.min(sediment)
+                } else {
+                    (sediment - capacity) * params.deposition
+                };
+                sediment -= deposit;
+                spread(heights, size, previous, deposit);
+            } else {
+                let erode = ((capacity - sediment) * params.erosion).min(-height_difference);
+                sediment += erode;
+                spread(heights, size, previous, -erode);
+            }
+
+            speed = (speed * speed - height_difference * params.gravity)
+                .max(0.0)
+                .sqrt();
+            water *= 1.0 - params.evaporation;
+        }
+        // Whatever is still carried settles where the droplet ends, so no terrain is lost.
+        spread(heights, size, position, sediment);
+    }
+}
+
+impl RegionErosion {
+    fn sample(&self, x: f64, z: f64) -> f64 {
+        let last = (self.size - 1) as f64;
+        let position = ((DVec2::new(x, z) - self.origin) / self.cell_size)
+            .clamp(DVec2::ZERO, DVec2::splat(last - 1e-6));
+        let cell = position.floor();
+        let t = position - cell;
+        let index = cell.y as usize * self.size + cell.x as usize;
+        let delta = |offset: usize| self.delta[index + offset] as f64;
+        delta(0) * (1.0 - t.x) * (1.0 - t.y)
+            + delta(1) * t.x * (1.0 - t.y)
+            + delta(self.size) * (1.0 - t.x) * t.y
+            + delta(self.size + 1) * t.x * t.y
+    }
+}
+
+// Bilinearly interpolated height at a point of the heightfield and its slope.
+fn height_and_gradient(heights: &[f64], size: usize, position: DVec2) -> (f64, DVec2) {
+    let cell = position.floor();
+    let t = position - cell;
+    let index = cell.y as usize * size + cell.x as usize;
+    let (nw, ne) = (heights[index], heights[index + 1]);
+    let (sw, se) = (heights[index + size], heights[index + size + 1]);
+
+    let gradient = DVec2::new(
+        (ne - nw) * (1.0 - t.y) + (se - sw) * t.y,
+        (sw - nw) * (1.0 - t.x) + (se - ne) * t.x,
+    );
+    let height = nw * (1.0 - t.x) * (1.0 - t.y)
+        + ne * t.x * (1.0 - t.y)
+        + sw * (1.0 - t.x) * t.y
+        + se * t.x * t.y;
+    (height, gradient)
+}
+
+// Adds `amount` to the four grid points around a position, weighted by how close they are.
+fn spread(heights: &mut [f64], size: usize, position: DVec2, amount: f64) {
+    let cell = position.floor();
+    let t = position - cell;
+    let index = cell.y as usize * size + cell.x as usize;
+    heights[index] += amount * (1.0 - t.x) * (1.0 - t.y);
+    heights[index + 1] += amount * t.x * (1.0 - t.y);
+    heights[index + size] += amount * (1.0 - t.x) * t.y;
+    heights[index + size + 1] += amount * t.x * t.y;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hills(x: f64, z: f64) -> f64 {
+        40.0 + (x * 0.05).sin() * 12.0
+            + (z * 0.07).cos() * 9.0
+            + (x * 0.013 + z * 0.021).sin() * 20.0
+    }
+
+    fn erosion(region_size: u32, cell_size: u32) -> HydraulicErosion {
+        HydraulicErosion::new(HydraulicErosionParams {
+            enabled: true,
+            region_size,
+            cell_size,
+            ..default()
+        })
+    }
+
+    #[test]
+    fn simulating_a_region_twice_gives_the_same_deltas() {
+        let erosion = erosion(64, 4);
+        let position = IVec2::new(3, -2);
+        let first = erosion.simulate(position, &hills);
+        let second = erosion.simulate(position, &hills);
+        assert!(first.delta.iter().any(|&delta| delta != 0.0));
+        assert_eq!(first.delta, second.delta);
+    }
+
+    #[test]
+    fn full_cache_drops_the_farthest_regions() {
+        let erosion = erosion(1, 4);
+        let count = MAX_CACHED_REGIONS as i32;
+        for x in 0..=count {
+            erosion.region(IVec2::new(x, 0), &hills);
+        }
+        let regions = erosion.regions.read().unwrap();
+        assert!(regions.len() <= MAX_CACHED_REGIONS);
+        assert!(regions.contains_key(&IVec2::new(count, 0)));
+        assert!(regions.contains_key(&IVec2::new(count - 1, 0)));
+        assert!(!regions.contains_key(&IVec2::ZERO));
+    }
+
+    #[test]
+    fn regions_smaller_than_a_cell_still_simulate() {
+        let erosion = erosion(1, 4);
+        let region = erosion.simulate(IVec2::ZERO, &hills);
+        assert_eq!(region.size, 2);
+        assert!(erosion.height_change(0.3, 0.7, hills).is_finite());
+    }
+}
@@ -0,0 +1,549 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::ErrorKind,
+};
+
+use bevy::{
+    input::{InputSystems, gamepad::GamepadInput},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{fly_controller::FlyController, settings::Settings};
+
+const BINDINGS_PATH: &str = "bindings.ron";
+
+// How far an analog input has to be pushed, after the dead zone, to count as pressed.
+const PRESS_THRESHOLD: f32 = 0.5;
+
+/// Turns the keys, mouse buttons and gamepad inputs bound in [`InputBindings`] into the state of
+/// the [`Action`]s the other systems read from [`ActionState`].
+///
+/// The bindings are loaded from the user config file on startup and written back whenever they
+/// change.
+pub struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputBindings::load())
+            .init_resource::<ActionState>()
+            .add_systems(
+                PreUpdate,
+                (update_action_state, capture_rebinding)
+                    .chain()
+                    .after(InputSystems),
+            )
+            .add_systems(
+                Update,
+                save_bindings.run_if(
+                    resource_changed::<InputBindings>.and(not(resource_added::<InputBindings>)),
+                ),
+            );
+    }
+}
+
+/// Something the user can do, bound to inputs by the [`InputBindings`].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    LookLeft,
+    LookRight,
+    LookUp,
+    LookDown,
+    Jump,
+    Sprint,
+    SpeedUp,
+    SpeedDown,
+    ToggleMovementMode,
+    Respawn,
+    CaptureCursor,
+    ReleaseCursor,
+    ToggleCameraHud,
+    ToggleChunkHud,
+    ToggleVoxelHud,
+    ToggleBiomeHud,
+    ToggleBiomeOverlay,
+    CycleHeatmap,
+    RegenerateWorld,
+    ToggleSplineEditor,
+    AddBookmark,
+    ToggleBookmarks,
+    RecordPath,
+    AddKeyframe,
+    PlayPath,
+    ToggleControls,
+    ToggleSettings,
+}
+
+impl Action {
+    pub const ALL: [Action; 33] = [
+        Action::MoveForward,
+        Action::MoveBack,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::LookLeft,
+        Action::LookRight,
+        Action::LookUp,
+        Action::LookDown,
+        Action::Jump,
+        Action::Sprint,
+        Action::SpeedUp,
+        Action::SpeedDown,
+        Action::ToggleMovementMode,
+        Action::Respawn,
+        Action::CaptureCursor,
+        Action::ReleaseCursor,
+        Action::ToggleCameraHud,
+        Action::ToggleChunkHud,
+        Action::ToggleVoxelHud,
+        Action::ToggleBiomeHud,
+        Action::ToggleBiomeOverlay,
+        Action::CycleHeatmap,
+        Action::RegenerateWorld,
+        Action::ToggleSplineEditor,
+        Action::AddBookmark,
+        Action::ToggleBookmarks,
+        Action::RecordPath,
+        Action::AddKeyframe,
+        Action::PlayPath,
+        Action::ToggleControls,
+        Action::ToggleSettings,
+    ];
+
+    // Keys and mouse buttons only move and turn the camera while the cursor is captured, so they
+    // can be used in the UI meanwhile. Gamepad inputs always do.
+    fn needs_capture(self) -> bool {
+        matches!(
+            self,
+            Action::MoveForward
+                | Action::MoveBack
+                | Action::MoveLeft
+                | Action::MoveRight
+                | Action::MoveUp
+                | Action::MoveDown
+                | Action::LookLeft
+                | Action::LookRight
+                | Action::LookUp
+                | Action::LookDown
+                | Action::Jump
+                | Action::Sprint
+        )
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveForward => "Move forward",
+            Action::MoveBack => "Move back",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::MoveUp => "Fly up",
+            Action::MoveDown => "Fly down",
+            Action::LookLeft => "Look left",
+            Action::LookRight => "Look right",
+            Action::LookUp => "Look up",
+            Action::LookDown => "Look down",
+            Action::Jump => "Jump",
+            Action::Sprint => "Sprint",
+            Action::SpeedUp => "Fly faster",
+            Action::SpeedDown => "Fly slower",
+            Action::ToggleMovementMode => "Fly / walk",
+            Action::Respawn => "Respawn",
+            Action::CaptureCursor => "Capture cursor",
+            Action::ReleaseCursor => "Release cursor",
+            Action::ToggleCameraHud => "Camera data",
+            Action::ToggleChunkHud => "Chunk data",
+            Action::ToggleVoxelHud => "Voxel data",
+            Action::ToggleBiomeHud => "Biome data",
+            Action::ToggleBiomeOverlay => "Biome overlay",
+            Action::CycleHeatmap => "Climate heatmap",
+            Action::RegenerateWorld => "Regenerate world",
+            Action::ToggleSplineEditor => "Spline editor",
+            Action::AddBookmark => "Add bookmark",
+            Action::ToggleBookmarks => "Bookmarks",
+            Action::RecordPath => "Record path",
+            Action::AddKeyframe => "Add keyframe",
+            Action::PlayPath => "Play path",
+            Action::ToggleControls => "Controls",
+            Action::ToggleSettings => "Settings",
+        }
+    }
+}
+
+/// Which way a gamepad axis has to be pushed to trigger an action.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AxisDirection {
+    Positive,
+    Negative,
+}
+
+/// An input an [`Action`] can be bound to.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Key(KeyCode),
+    /// A key pressed while holding either shift key.
+    ShiftKey(KeyCode),
+    Mouse(MouseButton),
+    GamepadButton(GamepadButton),
+    GamepadAxis(GamepadAxis, AxisDirection),
+}
+
+impl Binding {
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => key_label(*key),
+            Binding::ShiftKey(key) => format!("Shift+{}", key_label(*key)),
+            Binding::Mouse(button) => format!("Mouse {button:?}"),
+            Binding::GamepadButton(button) => format!("Pad {button:?}"),
+            Binding::GamepadAxis(axis, AxisDirection::Positive) => format!("Pad {axis:?}+"),
+            Binding::GamepadAxis(axis, AxisDirection::Negative) => format!("Pad {axis:?}-"),
+        }
+    }
+
+    fn is_gamepad(&self) -> bool {
+        matches!(self, Binding::GamepadButton(_) | Binding::GamepadAxis(..))
+    }
+
+    // How far the input is pushed, from 0 to 1, and whether it was pressed this frame.
+    fn read(&self, input: &Inputs, dead_zone: f32) -> (f32, bool) {
+        let shift = || {
+            input
+                .keys
+                .any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+        };
+        match *self {
+            Binding::Key(key) => (
+                pressed_value(input.keys.pressed(key)),
+                input.keys.just_pressed(key),
+            ),
+            Binding::ShiftKey(key) => (
+                pressed_value(input.keys.pressed(key) && shift()),
+                input.keys.just_pressed(key) && shift(),
+            ),
+            Binding::Mouse(button) => (
+                pressed_value(input.mouse.pressed(button)),
+                input.mouse.just_pressed(button),
+            ),
+            Binding::GamepadButton(button) => {
+                input
+                    .gamepads
+                    .iter()
+                    .fold((0.0, false), |(value, just_pressed), gamepad| {
+                        let analog = past_dead_zone(gamepad.get(button).unwrap_or(0.0), dead_zone);
+                        let pressed = pressed_value(gamepad.pressed(button));
+                        (
+                            value.max(analog).max(pressed),
+                            just_pressed || gamepad.just_pressed(button),
+                        )
+                    })
+            }
+            Binding::GamepadAxis(axis, direction) => {
+                let value = input
+                    .gamepads
+                    .iter()
+                    .map(|gamepad| {
+                        let value = stick_value(gamepad, axis, dead_zone);
+                        match direction {
+                            AxisDirection::Positive => value.max(0.0),
+                            AxisDirection::Negative => (-value).max(0.0),
+                        }
+                    })
+                    .fold(0.0, f32::max);
+                // Axes count as just pressed when crossing the threshold, see `update_action_state`.
+                (value, false)
+            }
+        }
+    }
+}
+
+fn key_label(key: KeyCode) -> String {
+    let name = format!("{key:?}");
+    match name
+        .strip_prefix("Key")
+        .or_else(|| name.strip_prefix("Digit"))
+    {
+        Some(short) => short.to_string(),
+        None => name,
+    }
+}
+
+fn pressed_value(pressed: bool) -> f32 {
+    if pressed { 1.0 } else { 0.0 }
+}
+
+fn past_dead_zone(value: f32, dead_zone: f32) -> f32 {
+    let dead_zone = dead_zone.clamp(0.0, 0.99);
+    ((value - dead_zone) / (1.0 - dead_zone)).clamp(0.0, 1.0)
+}
+
+// The value of a gamepad axis, from -1 to 1, with the dead zone applied to the tilt of the whole
+// stick it belongs to. Clipping each axis on its own would snap diagonal pushes to the axes.
+fn stick_value(gamepad: &Gamepad, axis: GamepadAxis, dead_zone: f32) -> f32 {
+    let other = match axis {
+        GamepadAxis::LeftStickX => Some(GamepadAxis::LeftStickY),
+        GamepadAxis::LeftStickY => Some(GamepadAxis::LeftStickX),
+        GamepadAxis::RightStickX => Some(GamepadAxis::RightStickY),
+        GamepadAxis::RightStickY => Some(GamepadAxis::RightStickX),
+        _ => None,
+    };
+    let value = gamepad.get(axis).unwrap_or(0.0);
+    let tilt = Vec2::new(
+        value,
+        other.and_then(|other| gamepad.get(other)).unwrap_or(0.0),
+    );
+    let length = tilt.length();
+    if length > 0.0 {
+        value * past_dead_zone(length, dead_zone) / length
+    } else {
+        0.0
+    }
+}
+
+/// The inputs bound to each [`Action`], persisting between sessions.
+///
+/// Actions missing from the config file keep their default bindings.
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct InputBindings(BTreeMap<Action, Vec<Binding>>);
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        use AxisDirection::{Negative, Positive};
+        use Binding::{GamepadAxis as Axis, GamepadButton as Pad, Key, Mouse, ShiftKey};
+
+        let bindings = Action::ALL.map(|action| {
+            let bindings = match action {
+                Action::MoveForward => {
+                    vec![Key(KeyCode::KeyW), Axis(GamepadAxis::LeftStickY, Positive)]
+                }
+                Action::MoveBack => {
+                    vec![Key(KeyCode::KeyS), Axis(GamepadAxis::LeftStickY, Negative)]
+                }
+                Action::MoveLeft => {
+                    vec![Key(KeyCode::KeyA), Axis(GamepadAxis::LeftStickX, Negative)]
+                }
+                Action::MoveRight => {
+                    vec![Key(KeyCode::KeyD), Axis(GamepadAxis::LeftStickX, Positive)]
+                }
+                Action::MoveUp => vec![Key(KeyCode::Space), Pad(GamepadButton::RightTrigger2)],
+                Action::MoveDown => vec![Key(KeyCode::ShiftLeft), Pad(GamepadButton::LeftTrigger2)],
+                Action::LookLeft => vec![Axis(GamepadAxis::RightStickX, Negative)],
+                Action::LookRight => vec![Axis(GamepadAxis::RightStickX, Positive)],
+                Action::LookUp => vec![Axis(GamepadAxis::RightStickY, Positive)],
+                Action::LookDown => vec![Axis(GamepadAxis::RightStickY, Negative)],
+                Action::Jump => vec![Key(KeyCode::Space), Pad(GamepadButton::South)],
+                Action::Sprint => vec![Key(KeyCode::ShiftLeft), Pad(GamepadButton::LeftThumb)],
+                Action::SpeedUp => vec![Pad(GamepadButton::RightTrigger)],
+                Action::SpeedDown => vec![Pad(GamepadButton::LeftTrigger)],
+                Action::ToggleMovementMode => vec![Key(KeyCode::KeyG)],
+                Action::Respawn => vec![Key(KeyCode::Home)],
+                Action::CaptureCursor => vec![Mouse(MouseButton::Left)],
+                Action::ReleaseCursor => vec![Key(KeyCode::Escape)],
+                Action::ToggleCameraHud => vec![Key(KeyCode::F1)],
+                Action::ToggleChunkHud => vec![Key(KeyCode::F2)],
+                Action::ToggleVoxelHud => vec![Key(KeyCode::F3)],
+                Action::ToggleBiomeHud => vec![Key(KeyCode::F4)],
+                Action::ToggleBiomeOverlay => vec![Key(KeyCode::F5)],
+                Action::CycleHeatmap => vec![ShiftKey(KeyCode::F5)],
+                Action::ToggleSplineEditor => vec![Key(KeyCode::F6)],
+                Action::AddBookmark => vec![Key(KeyCode::KeyB)],
+                Action::ToggleBookmarks => vec![Key(KeyCode::F7)],
+                Action::RecordPath => vec![Key(KeyCode::KeyR)],
+                Action::AddKeyframe => vec![Key(KeyCode::KeyK)],
+                Action::PlayPath => vec![Key(KeyCode::KeyP)],
+                Action::ToggleControls => vec![Key(KeyCode::F8)],
+                Action::RegenerateWorld => vec![Key(KeyCode::F9)],
+                Action::ToggleSettings => vec![Key(KeyCode::F10)],
+            };
+            (action, bindings)
+        });
+        Self(bindings.into())
+    }
+}
+
+impl InputBindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Binds `action` to `binding` instead of the other inputs of the same kind, so keyboard and
+    /// mouse bindings are kept when binding a gamepad input and the other way around.
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.0.entry(action).or_default();
+        bindings.retain(|bound| bound.is_gamepad() != binding.is_gamepad());
+        bindings.push(binding);
+    }
+
+    fn load() -> Self {
+        let mut bindings = InputBindings::default();
+        match fs::read_to_string(BINDINGS_PATH) {
+            Ok(contents) => match ron::from_str::<BTreeMap<Action, Vec<Binding>>>(&contents) {
+                Ok(loaded) => bindings.0.extend(loaded),
+                Err(e) => warn!("Ignoring invalid {BINDINGS_PATH}: {e}"),
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => warn!("Could not read {BINDINGS_PATH}: {e}"),
+        }
+        bindings
+    }
+
+    fn save(&self) {
+        let contents = match ron::ser::to_string_pretty(&self.0, ron::ser::PrettyConfig::default())
+        {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!("Could not serialize input bindings: {e}");
+                return;
+            }
+        };
+        if let Err(e) = fs::write(BINDINGS_PATH, contents) {
+            warn!("Could not write {BINDINGS_PATH}: {e}");
+        }
+    }
+}
+
+fn save_bindings(bindings: Res<InputBindings>) {
+    bindings.save();
+}
+
+/// How far each [`Action`] is pushed this frame, read by the systems instead of the raw inputs.
+#[derive(Resource, Default)]
+pub struct ActionState {
+    values: HashMap<Action, f32>,
+    just_pressed: HashSet<Action>,
+}
+
+impl ActionState {
+    /// How far the inputs bound to `action` are pushed, from 0 to 1.
+    pub fn value(&self, action: Action) -> f32 {
+        self.values.get(&action).copied().unwrap_or(0.0)
+    }
+
+    /// The value of `positive` minus that of `negative`, from -1 to 1.
+    pub fn axis(&self, negative: Action, positive: Action) -> f32 {
+        self.value(positive) - self.value(negative)
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.value(action) >= PRESS_THRESHOLD
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+/// While present, the next input pressed is bound to this action instead of triggering any.
+/// Escape cancels.
+#[derive(Resource, Clone, Copy)]
+pub struct Rebinding(pub Action);
+
+#[derive(bevy::ecs::system::SystemParam)]
+struct Inputs<'w, 's> {
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    gamepads: Query<'w, 's, &'static Gamepad>,
+}
+
+fn update_action_state(
+    input: Inputs,
+    bindings: Res<InputBindings>,
+    settings: Res<Settings>,
+    rebinding: Option<Res<Rebinding>>,
+    cameras: Query<&FlyController>,
+    mut state: ResMut<ActionState>,
+) {
+    let was_pressed: HashSet<Action> = Action::ALL
+        .into_iter()
+        .filter(|action| state.pressed(*action))
+        .collect();
+    state.values.clear();
+    state.just_pressed.clear();
+    // Nothing is triggered while waiting for the input to rebind an action to.
+    if rebinding.is_some() {
+        return;
+    }
+    let captured = cameras.iter().any(FlyController::is_captured);
+    for (action, bindings) in &bindings.0 {
+        let mut value = 0.0f32;
+        let mut just_pressed = false;
+        let bindings = bindings
+            .iter()
+            .filter(|binding| captured || binding.is_gamepad() || !action.needs_capture());
+        for binding in bindings {
+            let (binding_value, binding_just_pressed) =
+                binding.read(&input, settings.gamepad_dead_zone);
+            value = value.max(binding_value);
+            just_pressed |= binding_just_pressed;
+        }
+        if value > 0.0 {
+            state.values.insert(*action, value);
+        }
+        if just_pressed || (value >= PRESS_THRESHOLD && !was_pressed.contains(action)) {
+            state.just_pressed.insert(*action);
+        }
+    }
+}
+
+fn capture_rebinding(
+    mut commands: Commands,
+    input: Inputs,
+    rebinding: Option<Res<Rebinding>>,
+    mut bindings: ResMut<InputBindings>,
+) {
+    let Some(rebinding) = rebinding else {
+        return;
+    };
+    if input.keys.just_pressed(KeyCode::Escape) {
+        commands.remove_resource::<Rebinding>();
+        return;
+    }
+    // Shift+key bindings can only be set in the config file, as shift is bound on its own here.
+    let key = input
+        .keys
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key));
+    let mouse = || {
+        input
+            .mouse
+            .get_just_pressed()
+            .next()
+            .map(|b| Binding::Mouse(*b))
+    };
+    let gamepad = || {
+        input.gamepads.iter().find_map(|gamepad| {
+            let button = gamepad
+                .get_just_pressed()
+                .next()
+                .map(|b| Binding::GamepadButton(*b));
+            button.or_else(|| {
+                gamepad.get_analog_axes().find_map(|input| match input {
+                    GamepadInput::Axis(axis) => {
+                        let value = gamepad.get(*axis)?;
+                        let direction = if value >= PRESS_THRESHOLD {
+                            AxisDirection::Positive
+                        } else if value <= -PRESS_THRESHOLD {
+                            AxisDirection::Negative
+                        } else {
+                            return None;
+                        };
+                        Some(Binding::GamepadAxis(*axis, direction))
+                    }
+                    GamepadInput::Button(_) => None,
+                })
+            })
+        })
+    };
+    if let Some(binding) = key.or_else(mouse).or_else(gamepad) {
+        info!("Bound {:?} to {}", rebinding.0, binding.label());
+        bindings.rebind(rebinding.0, binding);
+        commands.remove_resource::<Rebinding>();
+    }
+}
@@ -1,15 +1,29 @@
 use bevy::{app::Plugin, prelude::*};
 
 use crate::{
-    environment::EnvironmentPlugin, fly_controller::FlyControllerPlugin,
-    loading::AssetLoaderPlugin, ui::UiPlugin, voxel::VoxelPlugin,
+    bookmarks::BookmarksPlugin, camera_path::CameraPathPlugin, environment::EnvironmentPlugin,
+    fly_controller::FlyControllerPlugin, input::ActionsPlugin, loading::AssetLoaderPlugin,
+    settings::SettingsPlugin, ui::UiPlugin, voxel::VoxelPlugin,
 };
 
+mod atlas;
+pub mod benchmark;
+mod bookmarks;
+pub mod camera_path;
 mod environment;
 mod fly_controller;
+mod hydraulic;
+pub mod input;
+pub mod light;
 mod loading;
+pub mod meshing;
+pub mod overlay;
+mod resource_pack;
+mod settings;
+pub mod terrain_pass;
 mod ui;
-mod voxel;
+pub mod voxel;
+pub mod world_params;
 
 #[derive(States, Default, Clone, Eq, PartialEq, Debug, Hash)]
 enum AppState {
@@ -23,11 +37,15 @@ pub struct MainPlugin;
 impl Plugin for MainPlugin {
     fn build(&self, app: &mut App) {
         app.init_state::<AppState>().add_plugins((
+            SettingsPlugin,
+            ActionsPlugin,
+            BookmarksPlugin,
             AssetLoaderPlugin,
             UiPlugin,
             VoxelPlugin,
             EnvironmentPlugin,
             FlyControllerPlugin,
+            CameraPathPlugin,
         ));
     }
 }
@@ -0,0 +1,265 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use bevy::prelude::*;
+use bevy_voxel_world::{custom_meshing::CHUNK_SIZE_U, prelude::WorldVoxel};
+use block_mesh::ndshape::{RuntimeShape, Shape};
+
+use crate::voxel::{BlockMaterial, is_opaque};
+
+pub const MAX_LIGHT: u8 = 15;
+
+/// Directions to the six neighbours of a voxel or chunk, in the order [`ChunkLight::compute`]
+/// expects the lights of neighbouring chunks.
+pub const NEIGHBOURS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+/// Sky and block light levels of every voxel of a chunk, including its one voxel padding.
+///
+/// Light is flood filled inside the chunk only. The padding is seeded from the lights of the
+/// neighbouring chunks that are already lit, or from open sky above the chunk when the chunk
+/// above is unknown. After relighting a chunk, neighbours whose padding no longer matches
+/// its border have to be relit as well, see [`ChunkLight::border_differs`].
+#[derive(Debug)]
+pub struct ChunkLight {
+    shape: UVec3,
+    sky: Box<[u8]>,
+    block: Box<[u8]>,
+}
+
+impl ChunkLight {
+    pub fn compute(
+        voxels: &[WorldVoxel<BlockMaterial>],
+        shape: UVec3,
+        neighbours: &[Option<Arc<ChunkLight>>; 6],
+    ) -> Self {
+        let linear = RuntimeShape::<u32, 3>::new(shape.to_array());
+        let last = shape - 1;
+        // Light loses one level per block, so coarse LODs lose it one voxel at a time.
+        let step = (CHUNK_SIZE_U / (shape.x - 2).max(1)).min(MAX_LIGHT as u32) as u8;
+
+        let mut sky = vec![0; voxels.len()].into_boxed_slice();
+        let mut block = vec![0; voxels.len()].into_boxed_slice();
+        let mut fixed = vec![false; voxels.len()];
+        let mut queue = VecDeque::new();
+
+        for (index, voxel) in voxels.iter().enumerate() {
+            let p = UVec3::from_array(linear.delinearize(index as u32));
+            if let WorldVoxel::Solid(material) = voxel {
+                block[index] = material.light_emission();
+            }
+
+            let on_border = p.cmpeq(UVec3::ZERO) | p.cmpeq(last);
+            if on_border.any() {
+                fixed[index] = true;
+                let neighbour = (on_border.bitmask().count_ones() == 1)
+                    .then(|| padding_neighbour(p, last))
+                    .and_then(|(side, mirrored)| {
+                        let light = neighbours[side].as_ref().filter(|l| l.shape == shape)?;
+                        Some((light, light.index(mirrored)))
+                    });
+                if is_opaque(voxel) {
+                    // Nothing to copy: opaque voxels don't hold light, at most emit it.
+                } else if let Some((light, mirrored)) = neighbour {
+                    sky[index] = light.sky[mirrored];
+                    block[index] = block[index].max(light.block[mirrored]);
+                } else if p.y == last.y {
+                    sky[index] = MAX_LIGHT;
+                }
+            }
+
+            if sky[index] > 0 || block[index] > 0 {
+                queue.push_back(index);
+            }
+        }
+
+        while let Some(index) = queue.pop_front() {
+            let p = UVec3::from_array(linear.delinearize(index as u32)).as_ivec3();
+            for direction in NEIGHBOURS {
+                let q = p + direction;
+                if q.cmplt(IVec3::ZERO).any() || q.cmpgt(last.as_ivec3()).any() {
+                    continue;
+                }
+                let target = linear.linearize(q.as_uvec3().to_array()) as usize;
+                let voxel = &voxels[target];
+                if fixed[target] || is_opaque(voxel) {
+                    continue;
+                }
+
+                let translucent =
+                    matches!(voxel, WorldVoxel::Solid(material) if material.is_translucent());
+                let cost = step + translucent as u8;
+                // Sunlight shines straight down through air without fading.
+                let sky_level =
+                    if direction == IVec3::NEG_Y && sky[index] == MAX_LIGHT && !translucent {
+                        MAX_LIGHT
+                    } else {
+                        sky[index].saturating_sub(cost)
+                    };
+                let block_level = block[index].saturating_sub(cost);
+
+                if sky_level > sky[target] || block_level > block[target] {
+                    sky[target] = sky[target].max(sky_level);
+                    block[target] = block[target].max(block_level);
+                    queue.push_back(target);
+                }
+            }
+        }
+
+        Self { shape, sky, block }
+    }
+
+    /// Sky and block light at the voxel with the given index in the chunk's voxel array.
+    pub fn at(&self, index: usize) -> (u8, u8) {
+        (self.sky[index], self.block[index])
+    }
+
+    /// Whether the light on our border facing `NEIGHBOURS[side]` differs from what the chunk on
+    /// that side assumed for its padding, meaning that chunk has to be relit.
+    pub fn border_differs(&self, neighbour: &ChunkLight, side: usize) -> bool {
+        if neighbour.shape != self.shape {
+            return false;
+        }
+        let direction = NEIGHBOURS[side];
+        let axis = direction.abs().max_position();
+        let last = self.shape[axis] - 1;
+        let (ours, theirs) = if direction[axis] > 0 {
+            (last - 1, 0)
+        } else {
+            (1, last)
+        };
+
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        (1..self.shape[a] - 1).any(|u| {
+            (1..self.shape[b] - 1).any(|v| {
+                let mut p = UVec3::ZERO;
+                p[a] = u;
+                p[b] = v;
+                p[axis] = ours;
+                let own = self.index(p);
+                p[axis] = theirs;
+                let other = neighbour.index(p);
+                self.sky[own] != neighbour.sky[other] || self.block[own] != neighbour.block[other]
+            })
+        })
+    }
+
+    fn index(&self, p: UVec3) -> usize {
+        RuntimeShape::<u32, 3>::new(self.shape.to_array()).linearize(p.to_array()) as usize
+    }
+}
+
+// Index into NEIGHBOURS of the chunk a padding voxel on a single face belongs to, and the
+// position of the same voxel in that chunk.
+fn padding_neighbour(p: UVec3, last: UVec3) -> (usize, UVec3) {
+    let axis = (p.cmpeq(UVec3::ZERO) | p.cmpeq(last))
+        .bitmask()
+        .trailing_zeros() as usize;
+    let mut mirrored = p;
+    if p[axis] == 0 {
+        mirrored[axis] = last[axis] - 1;
+        (axis * 2 + 1, mirrored)
+    } else {
+        mirrored[axis] = 1;
+        (axis * 2, mirrored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A full resolution chunk, where light falls by one level per voxel.
+    const SHAPE: UVec3 = UVec3::splat(CHUNK_SIZE_U + 2);
+    const STONE: WorldVoxel<BlockMaterial> = WorldVoxel::Solid(BlockMaterial::Stone);
+
+    fn voxels(fill: impl Fn(UVec3) -> WorldVoxel<BlockMaterial>) -> Vec<WorldVoxel<BlockMaterial>> {
+        let linear = RuntimeShape::<u32, 3>::new(SHAPE.to_array());
+        (0..linear.size())
+            .map(|index| fill(UVec3::from_array(linear.delinearize(index))))
+            .collect()
+    }
+
+    fn light(voxels: &[WorldVoxel<BlockMaterial>]) -> ChunkLight {
+        ChunkLight::compute(voxels, SHAPE, &Default::default())
+    }
+
+    fn at(light: &ChunkLight, p: UVec3) -> (u8, u8) {
+        light.at(light.index(p))
+    }
+
+    #[test]
+    fn covered_cave_is_dark() {
+        let cave = |p: UVec3| p.cmpge(UVec3::splat(10)).all() && p.cmplt(UVec3::splat(20)).all();
+        let light = light(&voxels(|p| if cave(p) { WorldVoxel::Air } else { STONE }));
+        for p in [UVec3::splat(10), UVec3::splat(15), UVec3::splat(19)] {
+            assert_eq!(at(&light, p), (0, 0));
+        }
+    }
+
+    #[test]
+    fn open_column_gets_full_skylight() {
+        let shaft = |p: UVec3| p.x == 17 && p.z == 17 && p.y > 5;
+        let light = light(&voxels(|p| if shaft(p) { WorldVoxel::Air } else { STONE }));
+        for y in 6..SHAPE.y {
+            assert_eq!(at(&light, UVec3::new(17, y, 17)).0, MAX_LIGHT, "at y = {y}");
+        }
+    }
+
+    #[test]
+    fn lava_light_falls_by_one_per_block() {
+        let tunnel = |p: UVec3| p.y == 17 && p.z == 17 && p.x > 0 && p.x < SHAPE.x - 1;
+        let light = light(&voxels(|p| match p {
+            _ if p == UVec3::new(5, 17, 17) => WorldVoxel::Solid(BlockMaterial::Lava),
+            _ if tunnel(p) => WorldVoxel::Air,
+            _ => STONE,
+        }));
+        assert_eq!(at(&light, UVec3::new(5, 17, 17)).1, 15);
+        for distance in 1..=15 {
+            let p = UVec3::new(5 + distance, 17, 17);
+            assert_eq!(at(&light, p), (0, 15 - distance as u8), "at {p}");
+        }
+    }
+
+    #[test]
+    fn translucent_blocks_dim_light_one_level_more() {
+        let tunnel = |p: UVec3| p.y == 17 && p.z == 17 && p.x > 0 && p.x < SHAPE.x - 1;
+        let light = light(&voxels(|p| match p {
+            _ if p == UVec3::new(5, 17, 17) => WorldVoxel::Solid(BlockMaterial::Lava),
+            _ if tunnel(p) => WorldVoxel::Solid(BlockMaterial::Water),
+            _ => STONE,
+        }));
+        for distance in 1..=7 {
+            let p = UVec3::new(5 + distance, 17, 17);
+            assert_eq!(at(&light, p).1, 15 - 2 * distance as u8, "at {p}");
+        }
+    }
+
+    #[test]
+    fn changed_border_is_reported() {
+        // Lava close to the +X border of an otherwise enclosed chunk lights its neighbour's
+        // padding through an opening in the wall.
+        let lit = voxels(|p| match p {
+            _ if p == UVec3::new(SHAPE.x - 4, 17, 17) => WorldVoxel::Solid(BlockMaterial::Lava),
+            _ if p.y == 17 && p.z == 17 && p.x > SHAPE.x - 4 => WorldVoxel::Air,
+            _ => STONE,
+        });
+        let dark = voxels(|_| STONE);
+        let lit = Arc::new(light(&lit));
+        let dark = Arc::new(light(&dark));
+
+        // The chunk on the +X side, padded from the lit chunk on its -X side.
+        let mut neighbours: [Option<Arc<ChunkLight>>; 6] = Default::default();
+        neighbours[1] = Some(Arc::clone(&lit));
+        let next = ChunkLight::compute(&voxels(|_| WorldVoxel::Air), SHAPE, &neighbours);
+        assert!(at(&next, UVec3::new(0, 17, 17)).1 > 0);
+
+        assert!(!lit.border_differs(&next, 0));
+        assert!(dark.border_differs(&next, 0));
+    }
+}
@@ -5,32 +5,52 @@ use bevy::{
 use std::io::Cursor;
 use winit::window::Icon;
 
-use gcd_voxel_game::MainPlugin;
+use gcd_voxel_game::{MainPlugin, benchmark::BenchmarkPlugin};
 
 fn main() {
-    App::new()
-        .add_plugins(
-            DefaultPlugins
-                .set(WindowPlugin {
-                    primary_window: Some(Window {
-                        title: "BevyCraft".to_string(), // ToDo
-                        // Bind to canvas included in `index.html`
-                        canvas: Some("#bevy".to_owned()),
-                        fit_canvas_to_parent: true,
-                        // Tells wasm not to override default event handling, like F5 and Ctrl+R
-                        prevent_default_event_handling: false,
-                        ..default()
-                    }),
-                    ..default()
-                })
-                .set(AssetPlugin {
-                    meta_check: AssetMetaCheck::Never,
+    let mut app = App::new();
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: Some(Window {
+                    title: "BevyCraft".to_string(), // ToDo
+                    // Bind to canvas included in `index.html`
+                    canvas: Some("#bevy".to_owned()),
+                    fit_canvas_to_parent: true,
+                    // Tells wasm not to override default event handling, like F5 and Ctrl+R
+                    prevent_default_event_handling: false,
                     ..default()
                 }),
-        )
-        .add_plugins(MainPlugin)
-        .add_systems(Startup, set_window_icon)
-        .run();
+                ..default()
+            })
+            .set(AssetPlugin {
+                meta_check: AssetMetaCheck::Never,
+                ..default()
+            }),
+    )
+    .add_plugins(MainPlugin)
+    .add_systems(Startup, set_window_icon);
+    if let Some(duration) = benchmark_duration(std::env::args().skip(1)) {
+        app.add_plugins(BenchmarkPlugin { duration });
+    }
+    app.run();
+}
+
+// `--benchmark [seconds]` flies the built-in benchmark route instead of taking input.
+fn benchmark_duration(mut args: impl Iterator<Item = String>) -> Option<f32> {
+    args.position(|arg| arg == "--benchmark")?;
+    let duration = match args.next() {
+        Some(seconds) => seconds
+            .parse()
+            .ok()
+            .filter(|&s: &f32| s > 0.0)
+            .unwrap_or_else(|| {
+                warn!("Ignoring invalid benchmark duration {seconds:?}");
+                BenchmarkPlugin::DEFAULT_DURATION
+            }),
+        None => BenchmarkPlugin::DEFAULT_DURATION,
+    };
+    Some(duration)
 }
 
 fn set_window_icon(
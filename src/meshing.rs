@@ -0,0 +1,771 @@
+use std::sync::Arc;
+
+use bevy::{
+    asset::RenderAssetUsages,
+    light::NotShadowCaster,
+    mesh::{Indices, PrimitiveTopology, VertexAttributeValues},
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task, futures_lite::future},
+};
+use bevy_voxel_world::{
+    custom_meshing::CHUNK_SIZE_F,
+    prelude::*,
+    rendering::{ATTRIBUTE_TEX_INDEX, VoxelWorldMaterialHandle},
+};
+use block_mesh::{
+    RIGHT_HANDED_Y_UP_CONFIG, UnorientedQuad,
+    ndshape::{RuntimeShape, Shape},
+};
+
+use crate::{
+    light::{ChunkLight, MAX_LIGHT, NEIGHBOURS},
+    overlay::{ChunkTint, OverlayMaterial, TerrainOverlay},
+    resource_pack::{TranslucentMaterialHandle, VoxelMaterial},
+    settings::Settings,
+    voxel::{BlockMaterial, RegenerateWorld, TerrainWorld, is_opaque},
+};
+
+// How far the surface of water sits below the top of its voxel, in voxels.
+const WATER_SURFACE_DROP: f32 = 0.125;
+
+// Brightness of a face in complete darkness, so caves aren't pitch black.
+const MIN_BRIGHTNESS: f32 = 0.04;
+// Brightness lost per light level.
+const LIGHT_FALLOFF: f32 = 0.8;
+// Lava light is warmer than daylight.
+const BLOCK_LIGHT_TINT: Vec3 = Vec3::new(1.0, 0.8, 0.6);
+// Brightness of a corner by how open it is, from tucked between two blocks (0) to nothing
+// around it (3).
+const AMBIENT_OCCLUSION: [f32; 4] = [0.45, 0.65, 0.82, 1.0];
+// Corner order of a quad turned a quarter, which moves its triangles to the other diagonal.
+const ROTATED_CORNERS: [u32; 4] = [1, 3, 0, 2];
+
+/// Lights and meshes the chunks of [`TerrainWorld`].
+///
+/// `bevy_voxel_world` only gets an empty mesh from our meshing delegate. Once a chunk's voxels
+/// land in the chunk map, this plugin flood fills its sky and block light (see [`ChunkLight`])
+/// and meshes it into child entities of the chunk: one for opaque blocks and one for water and
+/// ice using the alpha blended voxel material. The light is baked into the vertex colors.
+///
+/// Light crosses chunk borders, so whenever the border of a relit chunk changes, the
+/// neighbours on that side are relit and remeshed too. All chunks are remeshed when the
+/// [`MeshingOptions`] change.
+pub struct ChunkMeshingPlugin;
+
+impl Plugin for ChunkMeshingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkLightMap>()
+            .init_resource::<ChunksToRelight>()
+            .add_systems(
+                Update,
+                (
+                    remesh_all_chunks.run_if(
+                        resource_changed::<Settings>.or(resource_changed::<TerrainOverlay>),
+                    ),
+                    forget_all_chunk_light,
+                    queue_chunk_meshes,
+                    spawn_chunk_meshes,
+                )
+                    .chain(),
+            )
+            .add_observer(forget_chunk_light);
+    }
+}
+
+/// How chunk meshes are built, taken from the graphics [`Settings`] and the [`TerrainOverlay`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshingOptions {
+    /// Darken the corners of faces next to other blocks.
+    pub ambient_occlusion: bool,
+    /// Merge neighbouring faces with the same material and light into larger quads.
+    pub greedy: bool,
+    /// Debug coloring replacing the block textures of opaque faces.
+    pub overlay: TerrainOverlay,
+}
+
+impl MeshingOptions {
+    fn new(settings: &Settings, overlay: TerrainOverlay) -> Self {
+        Self {
+            ambient_occlusion: settings.ambient_occlusion,
+            greedy: settings.greedy_meshing,
+            overlay,
+        }
+    }
+}
+
+/// Mesh without any vertices, with the attributes the voxel material expects.
+pub fn empty_mesh() -> Mesh {
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, Vec::<[f32; 3]>::new())
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, Vec::<[f32; 3]>::new())
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, Vec::<[f32; 2]>::new())
+    .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, Vec::<[f32; 4]>::new())
+    .with_inserted_attribute(
+        ATTRIBUTE_TEX_INDEX,
+        VertexAttributeValues::Uint32x3(Vec::new()),
+    )
+    .with_inserted_indices(Indices::U32(Vec::new()))
+}
+
+/// Mesh of the opaque blocks of a chunk, or `None` if it has no visible opaque faces.
+///
+/// Faces are kept wherever the neighbouring voxel lets light through, including the faces
+/// behind translucent blocks (the seabed under water for example). With a `tint`, the vertex
+/// colors are multiplied by the overlay color of each voxel.
+pub fn opaque_mesh(
+    voxels: &[WorldVoxel<BlockMaterial>],
+    data_shape: UVec3,
+    light: &ChunkLight,
+    texture_index_mapper: &TextureIndexMapperFn<BlockMaterial>,
+    options: MeshingOptions,
+    tint: Option<&ChunkTint>,
+) -> Option<Mesh> {
+    mesh_faces(
+        voxels,
+        data_shape,
+        light,
+        texture_index_mapper,
+        options,
+        tint,
+        false,
+    )
+}
+
+/// Mesh of the translucent blocks of a chunk, or `None` if it has no visible translucent faces.
+///
+/// Faces between two voxels of the same material and faces against opaque blocks are culled.
+/// Water with nothing on top is lowered by [`WATER_SURFACE_DROP`] so it doesn't look like a
+/// solid block.
+pub fn translucent_mesh(
+    voxels: &[WorldVoxel<BlockMaterial>],
+    data_shape: UVec3,
+    light: &ChunkLight,
+    texture_index_mapper: &TextureIndexMapperFn<BlockMaterial>,
+    options: MeshingOptions,
+) -> Option<Mesh> {
+    mesh_faces(
+        voxels,
+        data_shape,
+        light,
+        texture_index_mapper,
+        options,
+        None,
+        true,
+    )
+}
+
+// A visible face of a voxel, as far as merging it with its neighbours is concerned.
+#[derive(Clone, Copy, PartialEq)]
+struct FaceKey {
+    material: BlockMaterial,
+    colors: [Vec3; 4],
+    surface_drop: bool,
+}
+
+impl FaceKey {
+    // Faces are only merged when they are evenly lit, since the light and occlusion of the
+    // corners in between would be lost.
+    fn mergeable(&self) -> bool {
+        self.colors.iter().all(|color| *color == self.colors[0])
+    }
+}
+
+fn mesh_faces(
+    voxels: &[WorldVoxel<BlockMaterial>],
+    data_shape: UVec3,
+    light: &ChunkLight,
+    texture_index_mapper: &TextureIndexMapperFn<BlockMaterial>,
+    options: MeshingOptions,
+    tint: Option<&ChunkTint>,
+    translucent: bool,
+) -> Option<Mesh> {
+    let shape = RuntimeShape::<u32, 3>::new(data_shape.to_array());
+    let voxel_size = CHUNK_SIZE_F / (data_shape - 2).max(UVec3::ONE).as_vec3();
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut tex_coords = Vec::new();
+    let mut colors = Vec::new();
+    let mut texture_indices = Vec::new();
+    let mut indices = Vec::new();
+
+    for face in &RIGHT_HANDED_Y_UP_CONFIG.faces {
+        let normal = IVec3::from_array(face.signed_normal().to_array());
+        let axis = normal.abs().max_position();
+        let unit_corners = face
+            .quad_corners(&UnorientedQuad {
+                minimum: [0; 3],
+                width: 1,
+                height: 1,
+            })
+            .map(|corner| UVec3::from_array(corner.to_array()));
+        let u_axis = (unit_corners[1] - unit_corners[0]).max_position();
+        let v_axis = (unit_corners[2] - unit_corners[0]).max_position();
+        let (width, height) = (data_shape[u_axis] - 2, data_shape[v_axis] - 2);
+
+        // Visible faces of one layer of voxels, indexed by their u and v coordinates.
+        let mut layer = vec![None; (width * height) as usize];
+        for depth in 1..data_shape[axis] - 1 {
+            for v in 0..height {
+                for u in 0..width {
+                    let mut p = UVec3::ZERO;
+                    p[axis] = depth;
+                    p[u_axis] = u + 1;
+                    p[v_axis] = v + 1;
+                    layer[(u + v * width) as usize] =
+                        visible_face(voxels, &shape, light, p, normal, options, translucent).map(
+                            |mut key| {
+                                if let Some(tint) = tint {
+                                    let color = tint.at(p);
+                                    key.colors = key.colors.map(|corner| corner * color);
+                                }
+                                key
+                            },
+                        );
+                }
+            }
+
+            for v in 0..height {
+                for u in 0..width {
+                    let Some(key) = layer[(u + v * width) as usize] else {
+                        continue;
+                    };
+
+                    let (mut quad_width, mut quad_height) = (1, 1);
+                    if options.greedy && key.mergeable() {
+                        let same = |u: u32, v: u32| layer[(u + v * width) as usize] == Some(key);
+                        while u + quad_width < width && same(u + quad_width, v) {
+                            quad_width += 1;
+                        }
+                        while v + quad_height < height
+                            && (u..u + quad_width).all(|u| same(u, v + quad_height))
+                        {
+                            quad_height += 1;
+                        }
+                    }
+                    for v in v..v + quad_height {
+                        for u in u..u + quad_width {
+                            layer[(u + v * width) as usize] = None;
+                        }
+                    }
+
+                    let mut minimum = UVec3::ZERO;
+                    minimum[axis] = depth;
+                    minimum[u_axis] = u + 1;
+                    minimum[v_axis] = v + 1;
+                    let quad = UnorientedQuad {
+                        minimum: minimum.to_array(),
+                        width: quad_width,
+                        height: quad_height,
+                    };
+                    let surface_drop = if key.surface_drop {
+                        WATER_SURFACE_DROP * voxel_size.y
+                    } else {
+                        0.0
+                    };
+
+                    let start = positions.len() as u32;
+                    positions.extend(face.quad_corners(&quad).map(|corner| {
+                        // Chunks are placed one voxel down from their position to make up for
+                        // the padding, like bevy_voxel_world does for its own meshes.
+                        let corner = Vec3::from_array(corner.as_vec3().to_array());
+                        let mut position = voxel_size * (corner - 1.0) + 1.0;
+                        if corner.y > minimum.y as f32 {
+                            position.y -= surface_drop;
+                        }
+                        position.to_array()
+                    }));
+                    normals.extend_from_slice(&face.quad_mesh_normals());
+                    // Texture coordinates count voxels across the quad, so textures repeat once
+                    // per voxel however many faces were merged.
+                    let (u_scale, v_scale) = (voxel_size[u_axis], voxel_size[v_axis]);
+                    tex_coords.extend(
+                        face.tex_coords(RIGHT_HANDED_Y_UP_CONFIG.u_flip_face, true, &quad)
+                            .map(|[u, v]| [u * u_scale, v * v_scale]),
+                    );
+                    colors.extend(
+                        key.colors
+                            .map(|color| color.extend(key.material.opacity()).to_array()),
+                    );
+                    texture_indices.extend([texture_index_mapper(key.material); 4]);
+
+                    // Split the quad along the diagonal with the brighter corners, so a lone
+                    // occluded corner darkens only its own triangle instead of a streak running
+                    // through the middle of the face.
+                    let mut quad_indices = face.quad_mesh_indices(start);
+                    let brightness = key.colors.map(|color| color.element_sum());
+                    if brightness[0] + brightness[3] > brightness[1] + brightness[2] {
+                        quad_indices =
+                            quad_indices.map(|i| start + ROTATED_CORNERS[(i - start) as usize]);
+                    }
+                    indices.extend_from_slice(&quad_indices);
+                }
+            }
+        }
+    }
+
+    if positions.is_empty() {
+        return None;
+    }
+
+    let mut mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    );
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, tex_coords);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.insert_attribute(
+        ATTRIBUTE_TEX_INDEX,
+        VertexAttributeValues::Uint32x3(texture_indices),
+    );
+    mesh.insert_indices(Indices::U32(indices));
+    Some(mesh)
+}
+
+// The face of the voxel at `p` facing `normal`, if it belongs in this pass and isn't hidden.
+fn visible_face(
+    voxels: &[WorldVoxel<BlockMaterial>],
+    shape: &RuntimeShape<u32, 3>,
+    light: &ChunkLight,
+    p: UVec3,
+    normal: IVec3,
+    options: MeshingOptions,
+    translucent: bool,
+) -> Option<FaceKey> {
+    let WorldVoxel::Solid(material) = voxels[shape.linearize(p.to_array()) as usize] else {
+        return None;
+    };
+    if material.is_translucent() != translucent {
+        return None;
+    }
+
+    let front = (p.as_ivec3() + normal).as_uvec3();
+    let visible = match voxels[shape.linearize(front.to_array()) as usize] {
+        WorldVoxel::Solid(other) if translucent => other.is_translucent() && other != material,
+        neighbour => !is_opaque(&neighbour),
+    };
+    if !visible {
+        return None;
+    }
+
+    let above = voxels[shape.linearize((p + UVec3::Y).to_array()) as usize];
+    let surface_drop = material == BlockMaterial::Water && !above.is_solid();
+
+    let axis = normal.abs().max_position();
+    let face = &RIGHT_HANDED_Y_UP_CONFIG.faces[face_index(normal)];
+    let corners = face.quad_corners(&UnorientedQuad {
+        minimum: p.to_array(),
+        width: 1,
+        height: 1,
+    });
+    let colors = corners.map(|corner| {
+        let corner = UVec3::from_array(corner.to_array());
+        vertex_color(voxels, shape, light, front, corner, axis, options)
+    });
+    Some(FaceKey {
+        material,
+        colors,
+        surface_drop,
+    })
+}
+
+// Index of the face with the given normal in RIGHT_HANDED_Y_UP_CONFIG: -X, -Y, -Z, +X, +Y, +Z.
+fn face_index(normal: IVec3) -> usize {
+    let axis = normal.abs().max_position();
+    if normal[axis] < 0 { axis } else { axis + 3 }
+}
+
+// Smooth lighting: the light at a corner is the average of the four voxels in front of the
+// face that touch it, leaving out the ones that block light. With ambient occlusion, the
+// corner is also darkened by the blocks among them.
+fn vertex_color(
+    voxels: &[WorldVoxel<BlockMaterial>],
+    shape: &RuntimeShape<u32, 3>,
+    light: &ChunkLight,
+    front: UVec3,
+    corner: UVec3,
+    axis: usize,
+    options: MeshingOptions,
+) -> Vec3 {
+    let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+    // Of the two voxel rows touching the corner, the one the front voxel is not in.
+    let beside = |i: usize| {
+        if corner[i] == front[i] {
+            corner[i] - 1
+        } else {
+            corner[i]
+        }
+    };
+    let sample = |along_a: bool, along_b: bool| {
+        let mut p = front;
+        if along_a {
+            p[a] = beside(a);
+        }
+        if along_b {
+            p[b] = beside(b);
+        }
+        let index = shape.linearize(p.to_array()) as usize;
+        (!is_opaque(&voxels[index])).then(|| light.at(index))
+    };
+
+    let side_a = sample(true, false);
+    let side_b = sample(false, true);
+    // Light doesn't squeeze through between two blocks touching at an edge.
+    let diagonal = if side_a.is_none() && side_b.is_none() {
+        None
+    } else {
+        sample(true, true)
+    };
+
+    let mut sky = 0.0;
+    let mut block = 0.0;
+    let mut count = 0.0;
+    for (sky_level, block_level) in [sample(false, false), side_a, side_b, diagonal]
+        .into_iter()
+        .flatten()
+    {
+        sky += sky_level as f32;
+        block += block_level as f32;
+        count += 1.0;
+    }
+    // The voxel in front of a visible face always lets light through, so count is never 0.
+    let sky = brightness(sky / count);
+    let block = brightness(block / count);
+    let color = Vec3::splat(sky).max(block * BLOCK_LIGHT_TINT);
+
+    if !options.ambient_occlusion {
+        return color;
+    }
+    let occlusion = match (side_a.is_none(), side_b.is_none()) {
+        (true, true) => 0,
+        (side_a, side_b) => 3 - side_a as usize - side_b as usize - diagonal.is_none() as usize,
+    };
+    color * AMBIENT_OCCLUSION[occlusion]
+}
+
+fn brightness(level: f32) -> f32 {
+    MIN_BRIGHTNESS + (1.0 - MIN_BRIGHTNESS) * LIGHT_FALLOFF.powf(MAX_LIGHT as f32 - level)
+}
+
+/// Light of every chunk that was lit, used to seed the light of their neighbours.
+#[derive(Resource, Default)]
+struct ChunkLightMap(HashMap<IVec3, (Entity, Arc<ChunkLight>)>);
+
+/// Chunks to relight and remesh, because the light of a neighbour changed along their shared
+/// border or the meshing options changed.
+#[derive(Resource, Default)]
+struct ChunksToRelight(HashSet<IVec3>);
+
+struct ChunkMeshes {
+    light: Arc<ChunkLight>,
+    opaque: Option<Mesh>,
+    translucent: Option<Mesh>,
+    // The opaque mesh is colored by the terrain overlay rather than textured.
+    overlay: bool,
+}
+
+#[derive(Component)]
+struct ChunkMeshingTask {
+    position: IVec3,
+    task: Task<ChunkMeshes>,
+}
+
+/// Child entity of a chunk holding one of the meshes built by [`ChunkMeshingPlugin`].
+#[derive(Component)]
+struct ChunkMeshPart;
+
+#[allow(clippy::too_many_arguments)]
+fn queue_chunk_meshes(
+    mut commands: Commands,
+    mut spawned_chunks: MessageReader<ChunkWillSpawn<TerrainWorld>>,
+    mut relight: ResMut<ChunksToRelight>,
+    light_map: Res<ChunkLightMap>,
+    voxel_world: VoxelWorld<TerrainWorld>,
+    terrain: Res<TerrainWorld>,
+    settings: Res<Settings>,
+    overlay: Res<TerrainOverlay>,
+) {
+    let thread_pool = AsyncComputeTaskPool::get();
+    let options = MeshingOptions::new(&settings, *overlay);
+    let mut positions: HashSet<IVec3> = relight.0.drain().collect();
+    positions.extend(spawned_chunks.read().map(|chunk| chunk.chunk_key));
+
+    for chunk_pos in positions {
+        let Some(chunk_data) = voxel_world.get_chunk_data(chunk_pos) else {
+            continue;
+        };
+        let data_shape = chunk_data.data_shape();
+        let voxels = chunk_data.voxels_arc();
+        // Chunks of a single voxel type have no faces between their voxels, but still need light
+        // for their neighbours.
+        let fill = match chunk_data.get_voxel(UVec3::ZERO) {
+            WorldVoxel::Unset => WorldVoxel::Air,
+            voxel => voxel,
+        };
+        let neighbours = NEIGHBOURS.map(|direction| {
+            light_map
+                .0
+                .get(&(chunk_pos + direction))
+                .map(|(_, light)| Arc::clone(light))
+        });
+        let texture_index_mapper = terrain.texture_index_mapper();
+        let overlay_terrain = (options.overlay != TerrainOverlay::Off).then(|| terrain.clone());
+
+        let task = thread_pool.spawn(async move {
+            let Some(voxels) = voxels else {
+                let voxels = vec![fill; data_shape.element_product() as usize];
+                return ChunkMeshes {
+                    light: Arc::new(ChunkLight::compute(&voxels, data_shape, &neighbours)),
+                    opaque: None,
+                    translucent: None,
+                    overlay: false,
+                };
+            };
+            let light = ChunkLight::compute(&voxels, data_shape, &neighbours);
+            let tint = overlay_terrain
+                .map(|terrain| ChunkTint::new(options.overlay, terrain, chunk_pos, data_shape));
+            ChunkMeshes {
+                opaque: opaque_mesh(
+                    &voxels,
+                    data_shape,
+                    &light,
+                    &texture_index_mapper,
+                    options,
+                    tint.as_ref(),
+                ),
+                overlay: tint.is_some(),
+                translucent: translucent_mesh(
+                    &voxels,
+                    data_shape,
+                    &light,
+                    &texture_index_mapper,
+                    options,
+                ),
+                light: Arc::new(light),
+            }
+        });
+        commands
+            .entity(chunk_data.get_entity())
+            .try_insert(ChunkMeshingTask {
+                position: chunk_pos,
+                task,
+            });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_chunk_meshes(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut ChunkMeshingTask, Option<&Children>)>,
+    mesh_parts: Query<(), With<ChunkMeshPart>>,
+    mut light_map: ResMut<ChunkLightMap>,
+    mut relight: ResMut<ChunksToRelight>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    opaque_material: Res<VoxelWorldMaterialHandle<VoxelMaterial>>,
+    translucent_material: Res<TranslucentMaterialHandle>,
+    overlay_material: Res<OverlayMaterial>,
+) {
+    for (chunk, mut task, children) in &mut tasks {
+        let Some(chunk_meshes) = future::block_on(future::poll_once(&mut task.task)) else {
+            continue;
+        };
+        commands.entity(chunk).remove::<ChunkMeshingTask>();
+
+        let chunk_pos = task.position;
+        for (side, direction) in NEIGHBOURS.iter().enumerate() {
+            let neighbour_pos = chunk_pos + direction;
+            if let Some((_, neighbour)) = light_map.0.get(&neighbour_pos)
+                && chunk_meshes.light.border_differs(neighbour, side)
+            {
+                relight.0.insert(neighbour_pos);
+            }
+        }
+        light_map.0.insert(chunk_pos, (chunk, chunk_meshes.light));
+
+        for child in children.into_iter().flatten() {
+            if mesh_parts.contains(*child) {
+                commands.entity(*child).despawn();
+            }
+        }
+
+        // Chunks that bevy_voxel_world didn't mesh have no visibility of their own to inherit.
+        commands.entity(chunk).insert_if_new(Visibility::default());
+        if let Some(mesh) = chunk_meshes.opaque {
+            let mut part =
+                commands.spawn((ChunkMeshPart, Mesh3d(meshes.add(mesh)), ChildOf(chunk)));
+            if chunk_meshes.overlay {
+                part.insert(MeshMaterial3d(overlay_material.0.clone()));
+            } else {
+                part.insert(MeshMaterial3d(opaque_material.handle.clone()));
+            }
+        }
+        if let Some(mesh) = chunk_meshes.translucent {
+            commands.spawn((
+                ChunkMeshPart,
+                Mesh3d(meshes.add(mesh)),
+                MeshMaterial3d(translucent_material.0.clone()),
+                NotShadowCaster,
+                ChildOf(chunk),
+            ));
+        }
+    }
+}
+
+// Rebuilds every chunk when a setting that changes how they're meshed is toggled.
+fn remesh_all_chunks(
+    settings: Res<Settings>,
+    overlay: Res<TerrainOverlay>,
+    mut meshed_with: Local<Option<MeshingOptions>>,
+    light_map: Res<ChunkLightMap>,
+    mut relight: ResMut<ChunksToRelight>,
+) {
+    let options = MeshingOptions::new(&settings, *overlay);
+    if meshed_with
+        .replace(options)
+        .is_some_and(|previous| previous != options)
+    {
+        relight.0.extend(light_map.0.keys().copied());
+    }
+}
+
+// The light of a regenerated world has nothing to do with the old one, so chunks that are still
+// meshing or not despawned yet must not seed their new neighbours.
+fn forget_all_chunk_light(
+    mut regenerate: MessageReader<RegenerateWorld>,
+    mut light_map: ResMut<ChunkLightMap>,
+    mut relight: ResMut<ChunksToRelight>,
+) {
+    if regenerate.read().count() > 0 {
+        light_map.0.clear();
+        relight.0.clear();
+    }
+}
+
+fn forget_chunk_light(
+    remove: On<Remove, Chunk<TerrainWorld>>,
+    chunks: Query<&Chunk<TerrainWorld>>,
+    mut light_map: ResMut<ChunkLightMap>,
+) {
+    let Ok(chunk) = chunks.get(remove.entity) else {
+        return;
+    };
+    if light_map
+        .0
+        .get(&chunk.position)
+        .is_some_and(|(entity, _)| *entity == remove.entity)
+    {
+        light_map.0.remove(&chunk.position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    // A small chunk of uneven ground: stone under patches of grass, sand and dirt at a few
+    // heights, with a pillar of wood in the middle.
+    fn sample_chunk() -> (Vec<WorldVoxel<BlockMaterial>>, UVec3) {
+        let data_shape = UVec3::splat(10);
+        let shape = RuntimeShape::<u32, 3>::new(data_shape.to_array());
+        let voxels = (0..shape.size())
+            .map(|index| {
+                let [x, y, z] = shape.delinearize(index);
+                let height = 2 + (x / 3 + z / 4) % 3;
+                let top = [
+                    BlockMaterial::Grass,
+                    BlockMaterial::Sand,
+                    BlockMaterial::Dirt,
+                ][((x / 2 + z / 3) % 3) as usize];
+                match y {
+                    _ if (4..6).contains(&x) && (4..6).contains(&z) && y <= 7 => {
+                        WorldVoxel::Solid(BlockMaterial::Wood)
+                    }
+                    _ if y < height => WorldVoxel::Solid(BlockMaterial::Stone),
+                    _ if y == height => WorldVoxel::Solid(top),
+                    _ => WorldVoxel::Air,
+                }
+            })
+            .collect();
+        (voxels, data_shape)
+    }
+
+    // Total area of the quads of a mesh, by face normal and texture indices.
+    fn face_areas(mesh: &Mesh) -> BTreeMap<([i32; 3], [u32; 3]), f32> {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("The mesh should have positions");
+        };
+        let Some(VertexAttributeValues::Float32x3(normals)) =
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+        else {
+            panic!("The mesh should have normals");
+        };
+        let Some(VertexAttributeValues::Uint32x3(textures)) = mesh.attribute(ATTRIBUTE_TEX_INDEX)
+        else {
+            panic!("The mesh should have texture indices");
+        };
+
+        let mut areas = BTreeMap::new();
+        for quad in (0..positions.len()).step_by(4) {
+            let [a, b, c] = [0, 1, 2].map(|corner| Vec3::from_array(positions[quad + corner]));
+            let normal = Vec3::from_array(normals[quad])
+                .round()
+                .as_ivec3()
+                .to_array();
+            *areas.entry((normal, textures[quad])).or_default() += (b - a).cross(c - a).length();
+        }
+        areas
+    }
+
+    fn mesh(options: MeshingOptions) -> Mesh {
+        let (voxels, data_shape) = sample_chunk();
+        let light = ChunkLight::compute(&voxels, data_shape, &Default::default());
+        let texture_index_mapper: TextureIndexMapperFn<BlockMaterial> =
+            Arc::new(|material| [material as u32; 3]);
+        opaque_mesh(
+            &voxels,
+            data_shape,
+            &light,
+            &texture_index_mapper,
+            options,
+            None,
+        )
+        .expect("The sample chunk should have visible faces")
+    }
+
+    #[test]
+    fn greedy_mesh_covers_the_same_faces() {
+        for ambient_occlusion in [false, true] {
+            let options = |greedy| MeshingOptions {
+                ambient_occlusion,
+                greedy,
+                overlay: TerrainOverlay::Off,
+            };
+            let per_face = mesh(options(false));
+            let greedy = mesh(options(true));
+            assert!(greedy.count_vertices() < per_face.count_vertices());
+
+            let (per_face, greedy) = (face_areas(&per_face), face_areas(&greedy));
+            assert_eq!(
+                per_face.keys().collect::<Vec<_>>(),
+                greedy.keys().collect::<Vec<_>>()
+            );
+            for (key, area) in &per_face {
+                assert!(
+                    (greedy[key] - area).abs() < 1e-2,
+                    "{key:?}: {} instead of {area}",
+                    greedy[key]
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::cell::RefCell;
+
+use bevy::{platform::collections::HashMap, prelude::*};
+use bevy_voxel_world::custom_meshing::{CHUNK_SIZE_I, CHUNK_SIZE_U};
+
+use crate::voxel::{Biome, ColumnSample, TerrainWorld};
+
+/// Debug render mode that colors the terrain by what the generator computed for each column
+/// instead of by block textures.
+///
+/// The overlay is switched with F5 and Shift+F5 in the debug UI. Chunks are remeshed whenever it
+/// changes, with their opaque faces using the untextured [`OverlayMaterial`].
+pub struct TerrainOverlayPlugin;
+
+impl Plugin for TerrainOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        let material = app
+            .world_mut()
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial {
+                base_color: Color::WHITE,
+                perceptual_roughness: 1.0,
+                reflectance: 0.0,
+                ..default()
+            });
+        app.init_resource::<TerrainOverlay>()
+            .insert_resource(OverlayMaterial(material));
+    }
+}
+
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TerrainOverlay {
+    #[default]
+    Off,
+    /// Each column in the color of its [`Biome`].
+    Biome,
+    /// A blue to red heatmap of one of the generator's values.
+    Heatmap(ClimateLayer),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClimateLayer {
+    Continent,
+    Erosion,
+    PeaksValleys,
+    Temperature,
+    Humidity,
+    Weirdness,
+    Height,
+}
+
+impl ClimateLayer {
+    pub const ALL: [ClimateLayer; 7] = [
+        ClimateLayer::Continent,
+        ClimateLayer::Erosion,
+        ClimateLayer::PeaksValleys,
+        ClimateLayer::Temperature,
+        ClimateLayer::Humidity,
+        ClimateLayer::Weirdness,
+        ClimateLayer::Height,
+    ];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL
+            .iter()
+            .position(|layer| *layer == self)
+            .unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    // Where the value of the layer falls between its lowest and highest value, from 0 to 1.
+    fn fraction(self, column: &ColumnSample) -> f64 {
+        let (value, min, max) = match self {
+            ClimateLayer::Continent => (column.continent, -1.0, 1.0),
+            ClimateLayer::Erosion => (column.erosion, -1.0, 1.0),
+            ClimateLayer::PeaksValleys => (column.peaks_valleys, -1.0, 1.0),
+            ClimateLayer::Temperature => (column.temperature, -1.0, 1.0),
+            ClimateLayer::Humidity => (column.humidity, -1.0, 1.0),
+            ClimateLayer::Weirdness => (column.weirdness, -1.0, 1.0),
+            ClimateLayer::Height => (column.height_offset, -128.0, 128.0),
+        };
+        ((value - min) / (max - min)).clamp(0.0, 1.0)
+    }
+}
+
+impl TerrainOverlay {
+    fn color(self, column: &ColumnSample) -> Vec3 {
+        match self {
+            TerrainOverlay::Off => Vec3::ONE,
+            TerrainOverlay::Biome => biome_color(column.biome),
+            TerrainOverlay::Heatmap(layer) => {
+                let hue = 240.0 * (1.0 - layer.fraction(column) as f32);
+                let color = Color::hsl(hue, 1.0, 0.5).to_linear();
+                Vec3::new(color.red, color.green, color.blue)
+            }
+        }
+    }
+}
+
+fn biome_color(biome: Biome) -> Vec3 {
+    let color = match biome {
+        Biome::Grassland => Color::srgb(0.55, 0.8, 0.3),
+        Biome::Forest => Color::srgb(0.1, 0.5, 0.15),
+        Biome::PineForest => Color::srgb(0.1, 0.35, 0.3),
+        Biome::Desert => Color::srgb(0.95, 0.85, 0.5),
+        Biome::Savanna => Color::srgb(0.8, 0.7, 0.3),
+        Biome::ScrubDesert => Color::srgb(0.75, 0.5, 0.3),
+        Biome::Taiga => Color::srgb(0.4, 0.55, 0.6),
+        Biome::Tundra => Color::srgb(0.9, 0.95, 1.0),
+        Biome::Ocean => Color::srgb(0.15, 0.3, 0.75),
+        Biome::DeepOcean => Color::srgb(0.05, 0.12, 0.45),
+        Biome::FrozenOcean => Color::srgb(0.55, 0.7, 0.9),
+        Biome::Beach => Color::srgb(0.95, 0.9, 0.65),
+        Biome::StonyShore => Color::srgb(0.5, 0.5, 0.5),
+        Biome::Mountains => Color::srgb(0.45, 0.4, 0.38),
+    }
+    .to_linear();
+    Vec3::new(color.red, color.green, color.blue)
+}
+
+/// The material of opaque chunk meshes while an overlay is shown, colored by vertex colors only.
+#[derive(Resource)]
+pub struct OverlayMaterial(pub Handle<StandardMaterial>);
+
+/// Overlay colors for the voxels of one chunk.
+pub struct ChunkTint {
+    overlay: TerrainOverlay,
+    terrain: TerrainWorld,
+    origin: IVec3,
+    stride: i32,
+    columns: RefCell<HashMap<IVec2, Vec3>>,
+}
+
+impl ChunkTint {
+    pub fn new(
+        overlay: TerrainOverlay,
+        terrain: TerrainWorld,
+        chunk_pos: IVec3,
+        data_shape: UVec3,
+    ) -> Self {
+        Self {
+            overlay,
+            terrain,
+            origin: chunk_pos * CHUNK_SIZE_I,
+            stride: (CHUNK_SIZE_U / (data_shape.x - 2).max(1)) as i32,
+            columns: default(),
+        }
+    }
+
+    /// Color of the voxel at `p` in the chunk's padded voxel array.
+    pub fn at(&self, p: UVec3) -> Vec3 {
+        let position = self.origin + (p.as_ivec3() - 1) * self.stride;
+        *self
+            .columns
+            .borrow_mut()
+            .entry(position.xz())
+            .or_insert_with(|| {
+                let column = self.terrain.sample_column(position.x, position.z);
+                self.overlay.color(&column)
+            })
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+
+use bevy::{
+    asset::LoadState,
+    image::ImageAddressMode,
+    mesh::MeshVertexBufferLayoutRef,
+    pbr::{ExtendedMaterial, MaterialExtension, MaterialExtensionKey, MaterialExtensionPipeline},
+    prelude::*,
+    render::render_resource::{
+        AsBindGroup, RenderPipelineDescriptor, SpecializedMeshPipelineError,
+    },
+    shader::{ShaderDefVal, ShaderRef},
+};
+use bevy_voxel_world::{
+    prelude::{Chunk, NeedsDespawn},
+    rendering::{VOXEL_TEXTURE_SHADER_HANDLE, VoxelWorldMaterialHandle, vertex_layout},
+};
+use serde::Deserialize;
+
+use crate::{
+    atlas::{AtlasPlugin, RESOURCE_PACKS, TextureAtlas, atlas_path},
+    settings::Settings,
+    voxel::{BlockMaterial, TerrainWorld},
+};
+
+pub const DEFAULT_RESOURCE_PACK: &str = "default";
+
+/// Keeps the voxel material and the texture mapping of [`TerrainWorld`] in sync with the resource
+/// pack selected in [`Settings`].
+///
+/// Switching packs swaps the texture of the opaque and translucent voxel materials once the new atlas is loaded,
+/// then despawns every chunk so they get remeshed with the new pack's texture indices.
+///
+/// The pack selected on startup is inserted as [`PendingResourcePack`] while building, so the
+/// voxel world can be configured with it.
+pub struct ResourcePackPlugin;
+
+impl Plugin for ResourcePackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            AtlasPlugin,
+            MaterialPlugin::<VoxelMaterial> {
+                prepass_enabled: false,
+                ..default()
+            },
+        ));
+
+        let pack = ResourcePack::load_or_default(&app.world().resource::<Settings>().resource_pack);
+        let texture = app
+            .world()
+            .resource::<AssetServer>()
+            .load(pack.atlas_path());
+        let translucent_material = app
+            .world_mut()
+            .resource_mut::<Assets<VoxelMaterial>>()
+            .add(translucent_voxel_material(texture.clone()));
+        app.insert_resource(TranslucentMaterialHandle(translucent_material))
+            .insert_resource(ActiveResourcePack(pack.name()))
+            .insert_resource(PendingResourcePack {
+                pack,
+                texture,
+                rebuild_chunks: false,
+            })
+            .add_systems(
+                Update,
+                (
+                    select_resource_pack.run_if(resource_changed::<Settings>),
+                    apply_resource_pack.run_if(resource_exists::<PendingResourcePack>),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// A named set of block textures: the atlas built from a directory in `assets/textures/packs`
+/// and the block to texture mapping from its optional `pack.ron`.
+#[derive(Debug, Clone)]
+pub struct ResourcePack {
+    name: &'static str,
+    atlas: TextureAtlas,
+    blocks: HashMap<BlockMaterial, [String; 3]>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct PackManifest {
+    // Top, sides and bottom texture names for materials that should not use the
+    // tiles named after them.
+    blocks: HashMap<BlockMaterial, [String; 3]>,
+}
+
+impl ResourcePack {
+    /// Names of all the packs that were built into the game.
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        RESOURCE_PACKS.iter().map(|pack| pack.name)
+    }
+
+    pub fn load(name: &str) -> Option<Self> {
+        let pack = RESOURCE_PACKS.iter().find(|pack| pack.name == name)?;
+        let manifest = match pack.manifest.map(ron::from_str::<PackManifest>) {
+            Some(Ok(manifest)) => manifest,
+            Some(Err(e)) => {
+                warn!("Ignoring invalid pack.ron of resource pack {name:?}: {e}");
+                PackManifest::default()
+            }
+            None => PackManifest::default(),
+        };
+        Some(Self {
+            name: pack.name,
+            atlas: TextureAtlas::generated(pack),
+            blocks: manifest.blocks,
+        })
+    }
+
+    /// Loads the pack with the given name, falling back to the default pack if there is none.
+    pub fn load_or_default(name: &str) -> Self {
+        Self::load(name).unwrap_or_else(|| {
+            warn!("Unknown resource pack {name:?}, using {DEFAULT_RESOURCE_PACK:?}");
+            Self::load(DEFAULT_RESOURCE_PACK).expect("the default resource pack is built in")
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn atlas_path(&self) -> String {
+        atlas_path(self.name)
+    }
+
+    // Resolves the textures of every material against the atlas once, so the mapper handed
+    // to the meshing threads is a plain table lookup. Missing tiles fall back to layer 0.
+    pub fn texture_index_table(&self) -> Vec<[u32; 3]> {
+        BlockMaterial::ALL
+            .iter()
+            .map(|mat| {
+                let names = match self.blocks.get(mat) {
+                    Some(names) => names.each_ref().map(String::as_str),
+                    None => mat.texture_names(),
+                };
+                names.map(|name| {
+                    self.atlas.index_of(name).unwrap_or_else(|| {
+                        warn!(
+                            "Resource pack {:?} has no texture {name:?} for {mat:?}, using layer 0",
+                            self.name
+                        );
+                        0
+                    })
+                })
+            })
+            .collect()
+    }
+}
+
+/// The voxel material used for all chunks. Same as the one `bevy_voxel_world` sets up by
+/// default, but owned by us so its texture can be swapped when the resource pack changes.
+pub type VoxelMaterial = ExtendedMaterial<StandardMaterial, VoxelTextureExtension>;
+
+#[derive(Asset, AsBindGroup, Debug, Clone, Default, TypePath)]
+pub struct VoxelTextureExtension {
+    #[texture(100, dimension = "2d_array")]
+    #[sampler(101)]
+    pub voxels_texture: Handle<Image>,
+}
+
+impl MaterialExtension for VoxelTextureExtension {
+    fn fragment_shader() -> ShaderRef {
+        VOXEL_TEXTURE_SHADER_HANDLE.into()
+    }
+
+    fn vertex_shader() -> ShaderRef {
+        VOXEL_TEXTURE_SHADER_HANDLE.into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialExtensionPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialExtensionKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if descriptor
+            .vertex
+            .shader_defs
+            .contains(&ShaderDefVal::Bool("PREPASS_PIPELINE".into(), true))
+        {
+            return Ok(());
+        }
+
+        let vertex_layout = layout.0.get_layout(&vertex_layout())?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        Ok(())
+    }
+}
+
+pub fn voxel_material(texture: Handle<Image>) -> VoxelMaterial {
+    ExtendedMaterial {
+        base: StandardMaterial {
+            reflectance: 0.05,
+            metallic: 0.05,
+            perceptual_roughness: 0.95,
+            ..default()
+        },
+        extension: VoxelTextureExtension {
+            voxels_texture: texture,
+        },
+    }
+}
+
+/// Alpha blended variant of [`voxel_material`] for water and ice.
+pub fn translucent_voxel_material(texture: Handle<Image>) -> VoxelMaterial {
+    let mut material = voxel_material(texture);
+    material.base.alpha_mode = AlphaMode::Blend;
+    // Keeps the water surface visible from below.
+    material.base.double_sided = true;
+    material.base.cull_mode = None;
+    material
+}
+
+/// The material of the translucent chunk meshes, sharing its texture with the voxel material.
+#[derive(Resource)]
+pub struct TranslucentMaterialHandle(pub Handle<VoxelMaterial>);
+
+/// The resource pack whose texture is currently bound to the voxel material.
+#[derive(Resource)]
+pub struct ActiveResourcePack(pub &'static str);
+
+/// A resource pack waiting for its atlas to finish loading before it gets applied.
+#[derive(Resource)]
+pub struct PendingResourcePack {
+    pub pack: ResourcePack,
+    pub texture: Handle<Image>,
+    /// Existing chunks were meshed with another pack's texture indices and need to be rebuilt.
+    pub rebuild_chunks: bool,
+}
+
+fn select_resource_pack(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    active: Res<ActiveResourcePack>,
+    pending: Option<Res<PendingResourcePack>>,
+    asset_server: Res<AssetServer>,
+) {
+    let requested = settings.resource_pack.as_str();
+    let current = pending.map_or(active.0, |pending| pending.pack.name());
+    if requested == current {
+        return;
+    }
+    let Some(pack) = ResourcePack::load(requested) else {
+        warn!("Unknown resource pack {requested:?}, keeping {current:?}");
+        return;
+    };
+    info!("Switching to resource pack {requested:?}");
+    commands.insert_resource(PendingResourcePack {
+        texture: asset_server.load(pack.atlas_path()),
+        pack,
+        rebuild_chunks: true,
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_resource_pack(
+    mut commands: Commands,
+    pending: Res<PendingResourcePack>,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<VoxelMaterial>>,
+    material_handle: Res<VoxelWorldMaterialHandle<VoxelMaterial>>,
+    translucent_material: Res<TranslucentMaterialHandle>,
+    mut terrain: ResMut<TerrainWorld>,
+    chunks: Query<Entity, With<Chunk<TerrainWorld>>>,
+) {
+    match asset_server.get_load_state(&pending.texture) {
+        Some(LoadState::Loaded) => {}
+        Some(LoadState::Failed(e)) => {
+            error!(
+                "Failed to load resource pack {:?}: {e}",
+                pending.pack.name()
+            );
+            commands.remove_resource::<PendingResourcePack>();
+            return;
+        }
+        _ => return,
+    }
+    let Some(image) = images.get_mut(&pending.texture) else {
+        return;
+    };
+
+    // Atlases that were active before have already been turned into array textures.
+    if image.texture_descriptor.size.depth_or_array_layers == 1 {
+        let descriptor = image.sampler.get_or_init_descriptor();
+        descriptor.address_mode_u = ImageAddressMode::Repeat;
+        descriptor.address_mode_v = ImageAddressMode::Repeat;
+        descriptor.address_mode_w = ImageAddressMode::Repeat;
+        image.reinterpret_stacked_2d_as_array(pending.pack.atlas.layers());
+    }
+
+    for handle in [&material_handle.handle, &translucent_material.0] {
+        if let Some(material) = materials.get_mut(handle) {
+            material.extension.voxels_texture = pending.texture.clone();
+        }
+    }
+
+    if pending.rebuild_chunks {
+        terrain.use_resource_pack(&pending.pack);
+        for chunk in &chunks {
+            commands.entity(chunk).try_insert(NeedsDespawn);
+        }
+    }
+
+    commands.insert_resource(ActiveResourcePack(pending.pack.name()));
+    commands.remove_resource::<PendingResourcePack>();
+}
@@ -0,0 +1,88 @@
+use std::{fs, io::ErrorKind};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{resource_pack::DEFAULT_RESOURCE_PACK, world_params::DEFAULT_PRESET};
+
+const SETTINGS_PATH: &str = "settings.ron";
+
+/// Loads the user settings on startup and writes them back whenever they change.
+///
+/// Added before the other plugins so they can read their initial configuration from
+/// [`Settings`] while being built.
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Settings::load()).add_systems(
+            Update,
+            save_settings.run_if(resource_changed::<Settings>.and(not(resource_added::<Settings>))),
+        );
+    }
+}
+
+/// User preferences that persist between sessions.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    /// Name of the resource pack directory the voxel textures are taken from.
+    pub resource_pack: String,
+    /// Darken the corners of blocks in chunk meshes.
+    pub ambient_occlusion: bool,
+    /// Merge coplanar faces of chunk meshes into larger quads.
+    pub greedy_meshing: bool,
+    /// Name of the world preset the terrain is generated with.
+    pub world_preset: String,
+    /// How far an analog gamepad input has to be pushed, from 0 to 1, before it triggers its
+    /// action.
+    pub gamepad_dead_zone: f32,
+    /// How fast the look actions turn the camera when fully pushed, in radians per second.
+    pub gamepad_look_sensitivity: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            resource_pack: DEFAULT_RESOURCE_PACK.to_string(),
+            ambient_occlusion: true,
+            greedy_meshing: true,
+            world_preset: DEFAULT_PRESET.to_string(),
+            gamepad_dead_zone: 0.15,
+            gamepad_look_sensitivity: 2.5,
+        }
+    }
+}
+
+impl Settings {
+    fn load() -> Self {
+        match fs::read_to_string(SETTINGS_PATH) {
+            Ok(contents) => ron::from_str(&contents).unwrap_or_else(|e| {
+                warn!("Ignoring invalid {SETTINGS_PATH}: {e}");
+                Settings::default()
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Settings::default(),
+            Err(e) => {
+                warn!("Could not read {SETTINGS_PATH}: {e}");
+                Settings::default()
+            }
+        }
+    }
+
+    fn save(&self) {
+        let contents = match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!("Could not serialize settings: {e}");
+                return;
+            }
+        };
+        if let Err(e) = fs::write(SETTINGS_PATH, contents) {
+            warn!("Could not write {SETTINGS_PATH}: {e}");
+        }
+    }
+}
+
+fn save_settings(settings: Res<Settings>) {
+    settings.save();
+}
@@ -0,0 +1,512 @@
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use bevy_voxel_world::{custom_meshing::CHUNK_SIZE_I, prelude::WorldVoxel};
+use noise::{NoiseFn, Perlin};
+
+use crate::{
+    voxel::{Biome, BlockMaterial, ColumnSample, RIVER_BANK_HEIGHT},
+    world_params::{CaveParams, CaveSettings, UndergroundParams, WorldParams},
+};
+
+/// One step of the noise generator. For every voxel of a chunk, the passes of the
+/// [`TerrainPipeline`] run in order, each one getting the voxel the passes before it decided on.
+pub trait TerrainPass: Send + Sync {
+    /// Adjusts what the generator computed for an x/z column, once per column and chunk before
+    /// any of its voxels. The sampled columns shown in the UI go through this too.
+    fn column(&self, _x: i32, _z: i32, _column: &mut ColumnSample) {}
+
+    /// The voxel at `context.pos`, given the one generated by the passes before.
+    fn voxel(
+        &self,
+        context: &mut VoxelContext,
+        voxel: WorldVoxel<BlockMaterial>,
+    ) -> WorldVoxel<BlockMaterial>;
+}
+
+/// What the passes know about the voxel being generated, shared along the pipeline.
+pub struct VoxelContext<'a> {
+    pub pos: IVec3,
+    pub column: &'a ColumnSample,
+    /// The 3D density noise at the voxel, set by the base terrain pass.
+    pub density: f64,
+    /// The caves that carved out the voxel, set by the caves pass.
+    pub caves: Option<CaveCarving>,
+}
+
+impl VoxelContext<'_> {
+    /// Terrain density `offset` blocks above the voxel, the ground is solid where it's positive.
+    /// It reuses the density noise of the voxel, so only the surface height changes with it.
+    pub fn density_above(&self, offset: f64) -> f64 {
+        let ColumnSample {
+            height_offset,
+            squashing_factor,
+            ..
+        } = *self.column;
+        self.density - (self.pos.y as f64 + offset - height_offset) * squashing_factor
+    }
+
+    /// Whether the voxel is at the top of the ground, with air or water above it.
+    pub fn is_surface(&self) -> bool {
+        self.density_above(0.0) > 0.0 && self.density_above(1.0) <= 0.0
+    }
+}
+
+/// Builds a pass from the world params, again every time the world is regenerated.
+pub type PassBuilder = Arc<dyn Fn(&WorldParams) -> Box<dyn TerrainPass> + Send + Sync>;
+
+/// The passes the noise generator runs, in order and by name. Plugins can add, remove and reorder
+/// passes, changing this resource regenerates the world with them.
+#[derive(Resource, Clone)]
+pub struct TerrainPipeline {
+    passes: Vec<(&'static str, PassBuilder)>,
+}
+
+impl Default for TerrainPipeline {
+    fn default() -> Self {
+        let mut pipeline = Self { passes: Vec::new() };
+        pipeline
+            .push("base_terrain", |params| {
+                Box::new(BaseTerrainPass::new(params))
+            })
+            .push("surface", |_| Box::new(SurfacePass))
+            .push("caves", |params| Box::new(CavesPass::new(params)))
+            .push("ores", |_| Box::new(OresPass))
+            .push("decoration", |_| Box::new(DecorationPass));
+        pipeline
+    }
+}
+
+impl TerrainPipeline {
+    pub fn names(&self) -> impl Iterator<Item = &'static str> {
+        self.passes.iter().map(|(name, _)| *name)
+    }
+
+    /// Adds a pass after all the others.
+    pub fn push(
+        &mut self,
+        name: &'static str,
+        builder: impl Fn(&WorldParams) -> Box<dyn TerrainPass> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.passes.push((name, Arc::new(builder)));
+        self
+    }
+
+    /// Adds a pass right before the named one, or last if there is none.
+    pub fn insert_before(
+        &mut self,
+        before: &str,
+        name: &'static str,
+        builder: impl Fn(&WorldParams) -> Box<dyn TerrainPass> + Send + Sync + 'static,
+    ) -> &mut Self {
+        let index = self.position(before).unwrap_or(self.passes.len());
+        self.passes.insert(index, (name, Arc::new(builder)));
+        self
+    }
+
+    /// Adds a pass right after the named one, or last if there is none.
+    pub fn insert_after(
+        &mut self,
+        after: &str,
+        name: &'static str,
+        builder: impl Fn(&WorldParams) -> Box<dyn TerrainPass> + Send + Sync + 'static,
+    ) -> &mut Self {
+        let index = self
+            .position(after)
+            .map_or(self.passes.len(), |index| index + 1);
+        self.passes.insert(index, (name, Arc::new(builder)));
+        self
+    }
+
+    /// Removes the named pass, returning its builder so it can be inserted elsewhere.
+    pub fn remove(&mut self, name: &str) -> Option<PassBuilder> {
+        let index = self.position(name)?;
+        Some(self.passes.remove(index).1)
+    }
+
+    pub fn build(&self, params: &WorldParams) -> Arc<[Box<dyn TerrainPass>]> {
+        self.passes
+            .iter()
+            .map(|(_, builder)| builder(params))
+            .collect()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        let position = self.passes.iter().position(|(pass, _)| *pass == name);
+        if position.is_none() {
+            warn!("No terrain pass named {name:?}");
+        }
+        position
+    }
+}
+
+/// The shape of the terrain: stone where the density noise is above the surface height of the
+/// column, bedrock at the bottom of the world and air everywhere else.
+pub struct BaseTerrainPass {
+    density: Perlin,
+    bottom_y: i32,
+    bedrock_thickness: u32,
+}
+
+impl BaseTerrainPass {
+    pub fn new(params: &WorldParams) -> Self {
+        Self {
+            density: Perlin::new(params.density_seed_a),
+            bottom_y: params.min_chunk_y * CHUNK_SIZE_I,
+            bedrock_thickness: params.underground.bedrock_thickness,
+        }
+    }
+
+    // The bedrock band is solid at the bottom of the world and gets sparser towards its top.
+    fn is_bedrock(&self, pos: IVec3) -> bool {
+        let Ok(height) = u32::try_from(pos.y - self.bottom_y) else {
+            return true;
+        };
+        if height >= self.bedrock_thickness {
+            return false;
+        }
+        let hash = (pos.x as u32).wrapping_mul(0x9e37_79b1)
+            ^ (pos.y as u32).wrapping_mul(0x85eb_ca6b)
+            ^ (pos.z as u32).wrapping_mul(0xc2b2_ae35);
+        let hash = (hash ^ hash >> 15).wrapping_mul(0x2c1b_3c6d);
+        (hash ^ hash >> 12) % self.bedrock_thickness >= height
+    }
+}
+
+impl TerrainPass for BaseTerrainPass {
+    fn voxel(
+        &self,
+        context: &mut VoxelContext,
+        _voxel: WorldVoxel<BlockMaterial>,
+    ) -> WorldVoxel<BlockMaterial> {
+        context.density = self.density.get((context.pos.as_dvec3() * 0.01).to_array());
+        if self.is_bedrock(context.pos) {
+            WorldVoxel::Solid(BlockMaterial::Bedrock)
+        } else if context.density_above(0.0) > 0.0 {
+            WorldVoxel::Solid(BlockMaterial::Stone)
+        } else {
+            WorldVoxel::Air
+        }
+    }
+}
+
+/// Covers the stone with the blocks of the biome, river beds and banks, and fills the air below
+/// the water level with water.
+pub struct SurfacePass;
+
+impl TerrainPass for SurfacePass {
+    fn voxel(
+        &self,
+        context: &mut VoxelContext,
+        voxel: WorldVoxel<BlockMaterial>,
+    ) -> WorldVoxel<BlockMaterial> {
+        let ColumnSample {
+            biome,
+            river,
+            water_level,
+            ..
+        } = *context.column;
+        let pos_y_64 = context.pos.y as f64;
+
+        match voxel {
+            WorldVoxel::Solid(BlockMaterial::Stone) => {}
+            WorldVoxel::Air if pos_y_64 < water_level => {
+                return WorldVoxel::Solid(BlockMaterial::Water);
+            }
+            _ => return voxel,
+        }
+
+        if context.density_above(1.0) <= 0.0 {
+            if river > 0.0 && pos_y_64 < water_level + RIVER_BANK_HEIGHT {
+                // Clay river beds with sandy banks
+                if pos_y_64 < water_level {
+                    WorldVoxel::Solid(BlockMaterial::Clay)
+                } else {
+                    WorldVoxel::Solid(BlockMaterial::Sand)
+                }
+            } else {
+                WorldVoxel::Solid(match biome {
+                    Biome::Grassland => BlockMaterial::Grass,
+                    Biome::Forest => BlockMaterial::Leaves,
+                    Biome::PineForest => BlockMaterial::Coal,
+                    Biome::Taiga => BlockMaterial::Wood,
+                    Biome::Desert => BlockMaterial::Sand,
+                    Biome::Savanna => BlockMaterial::Gold,
+                    Biome::ScrubDesert => BlockMaterial::Platinum,
+                    Biome::Tundra => BlockMaterial::Snow,
+                    Biome::Ocean | Biome::Beach => BlockMaterial::Sand,
+                    Biome::DeepOcean | Biome::FrozenOcean => BlockMaterial::Gravel,
+                    Biome::StonyShore | Biome::Mountains => BlockMaterial::Stone,
+                })
+            }
+        } else if context.density_above(5.0) <= 0.0 {
+            match biome {
+                Biome::Desert | Biome::Ocean | Biome::Beach => {
+                    WorldVoxel::Solid(BlockMaterial::Sand)
+                }
+                Biome::DeepOcean | Biome::FrozenOcean | Biome::StonyShore => {
+                    WorldVoxel::Solid(BlockMaterial::Gravel)
+                }
+                Biome::Mountains => WorldVoxel::Solid(BlockMaterial::Stone),
+                Biome::Taiga | Biome::Tundra => WorldVoxel::Solid(BlockMaterial::Snow),
+                _ => WorldVoxel::Solid(BlockMaterial::Dirt),
+            }
+        } else {
+            voxel
+        }
+    }
+}
+
+/// Carves the caves of the [`CaveSettings`] out of the ground, filling them with lava below the
+/// lava level, and with water below the sea when they breach its floor.
+pub struct CavesPass {
+    noise: CaveNoise,
+    underground: UndergroundParams,
+}
+
+impl CavesPass {
+    pub fn new(params: &WorldParams) -> Self {
+        Self {
+            noise: CaveNoise::new(params),
+            underground: params.underground,
+        }
+    }
+}
+
+impl TerrainPass for CavesPass {
+    fn voxel(
+        &self,
+        context: &mut VoxelContext,
+        voxel: WorldVoxel<BlockMaterial>,
+    ) -> WorldVoxel<BlockMaterial> {
+        let ColumnSample {
+            height_offset,
+            water_level,
+            ..
+        } = *context.column;
+        let pos_y_64 = context.pos.y as f64;
+        let breach_sea_floor = self.noise.settings.breach_sea_floor;
+
+        let WorldVoxel::Solid(material) = voxel else {
+            return voxel;
+        };
+        let carvable = match material {
+            BlockMaterial::Water
+            | BlockMaterial::Ice
+            | BlockMaterial::Lava
+            | BlockMaterial::Bedrock => false,
+            // The subsurface blocks under sea and river level hold the water up, unless caves
+            // may breach the sea floor and flood. At least until we implement more fluid stuff
+            BlockMaterial::Sand
+            | BlockMaterial::Snow
+            | BlockMaterial::Dirt
+            | BlockMaterial::Clay
+            | BlockMaterial::Gravel => breach_sea_floor || pos_y_64 >= water_level,
+            _ => true,
+        };
+        if !carvable || pos_y_64 > height_offset + 1.0 {
+            return voxel;
+        }
+
+        let caves = self.noise.carving(context.pos);
+        if !caves.any() {
+            return voxel;
+        }
+        context.caves = Some(caves);
+
+        // Deep caves hold lava lakes, caves below the sea or a river are flooded.
+        if self.underground.lava_lakes && context.pos.y < self.underground.lava_level {
+            WorldVoxel::Solid(BlockMaterial::Lava)
+        } else if breach_sea_floor && height_offset < water_level && pos_y_64 < water_level {
+            WorldVoxel::Solid(BlockMaterial::Water)
+        } else {
+            WorldVoxel::Air
+        }
+    }
+}
+
+/// Fills the parts of cheese caves no other cave crosses with ore, depending on the climate.
+pub struct OresPass;
+
+impl OresPass {
+    fn ore(column: &ColumnSample) -> Option<BlockMaterial> {
+        let ColumnSample {
+            temperature: temp_val,
+            humidity: humidity_val,
+            weirdness: weirdness_val,
+            ..
+        } = *column;
+        match temp_val {
+            t if t < -0.5 => match humidity_val {
+                h if h < -0.5 => Some(BlockMaterial::Copper),
+                h if h < -0.1 => Some(BlockMaterial::Adamantine),
+                h if h < 0.1 => Some(BlockMaterial::Iron),
+                h if h < 0.5 => Some(BlockMaterial::Marble),
+                _ => None,
+            },
+            t if t < 0.5 => match humidity_val {
+                h if h < -0.5 => match weirdness_val {
+                    w if w < -0.5 => Some(BlockMaterial::Coal),
+                    w if w < -0.1 => Some(BlockMaterial::Wood),
+                    w if w < 0.1 => Some(BlockMaterial::Gold),
+                    w if w < 0.5 => Some(BlockMaterial::Tin),
+                    _ => None,
+                },
+                h if h < -0.1 => match weirdness_val {
+                    w if w < -0.5 => Some(BlockMaterial::Copper),
+                    w if w < 0.5 => Some(BlockMaterial::Iron),
+                    _ => None,
+                },
+                h if h < 0.1 => match weirdness_val {
+                    w if w < -0.5 => Some(BlockMaterial::Clay),
+                    w if w < 0.5 => Some(BlockMaterial::Dirt),
+                    _ => None,
+                },
+                h if h < 0.5 => match weirdness_val {
+                    w if w < -0.5 => Some(BlockMaterial::Silver),
+                    w if w < 0.5 => Some(BlockMaterial::Gold),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl TerrainPass for OresPass {
+    fn voxel(
+        &self,
+        context: &mut VoxelContext,
+        voxel: WorldVoxel<BlockMaterial>,
+    ) -> WorldVoxel<BlockMaterial> {
+        let Some(caves) = context.caves else {
+            return voxel;
+        };
+        if caves.cheese && !caves.meatballs && !caves.spaghetti && !caves.noodles {
+            Self::ore(context.column).map_or(voxel, WorldVoxel::Solid)
+        } else {
+            voxel
+        }
+    }
+}
+
+/// Covers the ground above the snow line with snow and freezes the surface of cold water.
+pub struct DecorationPass;
+
+impl TerrainPass for DecorationPass {
+    fn voxel(
+        &self,
+        context: &mut VoxelContext,
+        voxel: WorldVoxel<BlockMaterial>,
+    ) -> WorldVoxel<BlockMaterial> {
+        let ColumnSample {
+            biome,
+            water_level,
+            snow,
+            ..
+        } = *context.column;
+        let pos_y_64 = context.pos.y as f64;
+
+        match voxel {
+            WorldVoxel::Solid(BlockMaterial::Water) => {
+                let frozen = snow || matches!(biome, Biome::Tundra | Biome::FrozenOcean);
+                if frozen && pos_y_64 + 1.0 >= water_level {
+                    WorldVoxel::Solid(BlockMaterial::Ice)
+                } else {
+                    voxel
+                }
+            }
+            WorldVoxel::Solid(
+                BlockMaterial::Ice | BlockMaterial::Lava | BlockMaterial::Bedrock,
+            ) => voxel,
+            // Above the snow line, whatever the biome
+            WorldVoxel::Solid(_) if snow && pos_y_64 >= water_level && context.is_surface() => {
+                WorldVoxel::Solid(BlockMaterial::Snow)
+            }
+            _ => voxel,
+        }
+    }
+}
+
+/// Which of the cave shapes carve out a voxel.
+#[derive(Debug, Clone, Copy)]
+pub struct CaveCarving {
+    pub cheese: bool,
+    pub meatballs: bool,
+    pub spaghetti: bool,
+    pub noodles: bool,
+}
+
+impl CaveCarving {
+    pub fn any(&self) -> bool {
+        self.cheese || self.meatballs || self.spaghetti || self.noodles
+    }
+}
+
+// The 3D noise carving the caves.
+pub(crate) struct CaveNoise {
+    cheese: Perlin,
+    warp: Perlin,
+    spaghetti_a: Perlin,
+    spaghetti_b: Perlin,
+    noodle_a: Perlin,
+    noodle_b: Perlin,
+    settings: CaveSettings,
+}
+
+impl CaveNoise {
+    pub(crate) fn new(params: &WorldParams) -> Self {
+        Self {
+            cheese: Perlin::new(params.density_seed_b),
+            warp: Perlin::new(params.density_seed_c),
+            spaghetti_a: Perlin::new(params.spaghetti_seed_a),
+            spaghetti_b: Perlin::new(params.spaghetti_seed_b),
+            noodle_a: Perlin::new(params.noodle_seed_a),
+            noodle_b: Perlin::new(params.noodle_seed_b),
+            settings: params.caves,
+        }
+    }
+
+    pub(crate) fn carving(&self, pos: IVec3) -> CaveCarving {
+        let caves = &self.settings;
+        let point = pos.as_dvec3();
+        let sample = |noise: &Perlin, frequency: f64| noise.get((point * frequency).to_array());
+        let carves = |cave: &CaveParams| cave.enabled && (cave.min_y..=cave.max_y).contains(&pos.y);
+
+        // The meatballs need the spaghetti noise even when spaghetti caves are disabled.
+        let (spaghetti_a_val, spaghetti_b_val) =
+            if carves(&caves.spaghetti) || carves(&caves.meatballs) {
+                (
+                    sample(&self.spaghetti_a, caves.spaghetti.frequency).abs(),
+                    sample(&self.spaghetti_b, caves.spaghetti.frequency).abs(),
+                )
+            } else {
+                (1.0, 1.0)
+            };
+
+        let cheese = carves(&caves.cheese)
+            && sample(&self.cheese, caves.cheese.frequency) > caves.cheese.threshold;
+
+        let meatballs = carves(&caves.meatballs) && {
+            let cave_warp = sample(&self.warp, caves.meatballs.frequency);
+            cave_warp + spaghetti_a_val < caves.meatballs.threshold
+                && cave_warp + spaghetti_b_val < caves.meatballs.threshold
+        };
+
+        let spaghetti = carves(&caves.spaghetti)
+            && spaghetti_a_val < caves.spaghetti.threshold
+            && spaghetti_b_val < caves.spaghetti.threshold;
+
+        let noodles = carves(&caves.noodles)
+            && sample(&self.noodle_a, caves.noodles.frequency).abs() < caves.noodles.threshold
+            && sample(&self.noodle_b, caves.noodles.frequency).abs() < caves.noodles.threshold;
+
+        CaveCarving {
+            cheese,
+            meatballs,
+            spaghetti,
+            noodles,
+        }
+    }
+}
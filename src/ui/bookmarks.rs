@@ -0,0 +1,220 @@
+use bevy::prelude::*;
+use bevy_voxel_world::prelude::VoxelWorldCamera;
+
+use crate::{
+    AppState,
+    bookmarks::{Bookmark, Bookmarks},
+    fly_controller::{FlyController, Teleport},
+    input::{Action, ActionState},
+    loading::FontAssets,
+    ui::{OverlayColor, TextOptions},
+    voxel::TerrainWorld,
+    world_params::WorldPreset,
+};
+
+pub struct BookmarksUiPlugin;
+
+impl Plugin for BookmarksUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::Ready), setup)
+            .add_systems(
+                Update,
+                (
+                    toggle_bookmarks_panel,
+                    add_bookmark,
+                    press_bookmark_buttons,
+                    list_bookmarks
+                        .run_if(resource_changed::<Bookmarks>.or(resource_changed::<WorldPreset>)),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Ready)),
+            );
+    }
+}
+
+#[derive(Component)]
+struct BookmarksPanel;
+
+// Holds a row per bookmark of the current world.
+#[derive(Component)]
+struct BookmarkList;
+
+/// A button acting on the bookmarks of the current world, by index.
+#[derive(Component, Clone, Copy)]
+enum BookmarkButton {
+    Add,
+    Teleport(usize),
+    Remove(usize),
+}
+
+fn text_font(fonts: &FontAssets) -> TextFont {
+    TextFont {
+        font: fonts.vt323_regular.clone(),
+        font_size: TextOptions::DATA_TEXT_SIZE,
+        ..default()
+    }
+}
+
+fn setup(mut commands: Commands, fonts: Res<FontAssets>) {
+    let text_font = text_font(&fonts);
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                // Below the settings panel, growing upwards with the bookmarks.
+                bottom: Val::Px(10.0),
+                right: Val::Px(10.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(6.0)),
+                ..default()
+            },
+            BackgroundColor(OverlayColor::BG_COLOR),
+            Visibility::Hidden,
+            BookmarksPanel,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("Bookmarks"),
+                text_font.clone(),
+                TextColor(OverlayColor::YELLOW),
+            ));
+            parent.spawn((
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(2.0),
+                    ..default()
+                },
+                BookmarkList,
+            ));
+            parent
+                .spawn((Button, BackgroundColor(Color::NONE), BookmarkButton::Add))
+                .with_child((
+                    Text::new("Add bookmark here"),
+                    text_font,
+                    TextColor(OverlayColor::YELLOW),
+                ));
+        });
+}
+
+// Release the cursor to click the entries of the shown bookmarks panel.
+fn toggle_bookmarks_panel(
+    actions: Res<ActionState>,
+    mut panel: Single<&mut Visibility, With<BookmarksPanel>>,
+) {
+    if actions.just_pressed(Action::ToggleBookmarks) {
+        panel.toggle_visible_hidden();
+    }
+}
+
+fn add_bookmark(
+    actions: Res<ActionState>,
+    buttons: Query<(&Interaction, &BookmarkButton), Changed<Interaction>>,
+    camera: Single<(&FlyController, &Transform), With<VoxelWorldCamera<TerrainWorld>>>,
+    terrain: Res<TerrainWorld>,
+    preset: Res<WorldPreset>,
+    mut bookmarks: ResMut<Bookmarks>,
+) {
+    let pressed = buttons.iter().any(|(interaction, button)| {
+        *interaction == Interaction::Pressed && matches!(button, BookmarkButton::Add)
+    });
+    if !pressed && !actions.just_pressed(Action::AddBookmark) {
+        return;
+    }
+    let (controller, transform) = *camera;
+    let position = transform.translation;
+    // Named after the biome below, numbered to tell apart bookmarks in the same biome.
+    let biome = terrain
+        .sample_column(position.x.floor() as i32, position.z.floor() as i32)
+        .biome;
+    let number = bookmarks.of_world(&preset.0).len() + 1;
+    let bookmark = Bookmark {
+        name: format!("{number}. {biome:?}"),
+        position,
+        yaw: controller.yaw(),
+        pitch: controller.pitch(),
+    };
+    info!("Bookmarked {} at {position}", bookmark.name);
+    bookmarks.add(&preset.0, bookmark);
+}
+
+fn press_bookmark_buttons(
+    buttons: Query<(&Interaction, &BookmarkButton), Changed<Interaction>>,
+    preset: Res<WorldPreset>,
+    mut bookmarks: ResMut<Bookmarks>,
+    mut teleport: MessageWriter<Teleport>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match *button {
+            BookmarkButton::Add => {}
+            BookmarkButton::Teleport(index) => {
+                if let Some(bookmark) = bookmarks.of_world(&preset.0).get(index) {
+                    teleport.write(Teleport {
+                        position: bookmark.position,
+                        yaw: bookmark.yaw,
+                        pitch: bookmark.pitch,
+                    });
+                }
+            }
+            BookmarkButton::Remove(index) => bookmarks.remove(&preset.0, index),
+        }
+    }
+}
+
+fn list_bookmarks(
+    mut commands: Commands,
+    fonts: Res<FontAssets>,
+    bookmarks: Res<Bookmarks>,
+    preset: Res<WorldPreset>,
+    list: Single<Entity, With<BookmarkList>>,
+) {
+    let text_font = text_font(&fonts);
+    commands
+        .entity(*list)
+        .despawn_related::<Children>()
+        .with_children(|list| {
+            let bookmarks = bookmarks.of_world(&preset.0);
+            if bookmarks.is_empty() {
+                list.spawn((
+                    Text::new("None yet"),
+                    text_font.clone(),
+                    TextColor(OverlayColor::GREEN),
+                ));
+            }
+            for (index, bookmark) in bookmarks.iter().enumerate() {
+                let position = bookmark.position.round();
+                list.spawn(Node {
+                    column_gap: Val::Px(12.0),
+                    ..default()
+                })
+                .with_children(|row| {
+                    row.spawn((
+                        Button,
+                        BackgroundColor(Color::NONE),
+                        BookmarkButton::Teleport(index),
+                    ))
+                    .with_child((
+                        Text::new(format!(
+                            "{} ({}, {}, {})",
+                            bookmark.name, position.x, position.y, position.z
+                        )),
+                        text_font.clone(),
+                        TextColor(OverlayColor::GREEN),
+                    ));
+                    row.spawn((
+                        Button,
+                        BackgroundColor(Color::NONE),
+                        BookmarkButton::Remove(index),
+                    ))
+                    .with_child((
+                        Text::new("x"),
+                        text_font.clone(),
+                        TextColor(OverlayColor::RED),
+                    ));
+                });
+            }
+        });
+}
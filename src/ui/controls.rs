@@ -0,0 +1,169 @@
+use bevy::prelude::*;
+
+use crate::{
+    AppState,
+    input::{Action, ActionState, InputBindings, Rebinding},
+    loading::FontAssets,
+    ui::{OverlayColor, TextOptions},
+};
+
+pub struct ControlsUiPlugin;
+
+impl Plugin for ControlsUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::Ready), setup)
+            .add_systems(
+                Update,
+                (
+                    toggle_controls_panel,
+                    press_control_buttons,
+                    update_control_labels.run_if(
+                        resource_changed::<InputBindings>
+                            .or(resource_exists_and_changed::<Rebinding>)
+                            .or(resource_removed::<Rebinding>),
+                    ),
+                )
+                    .run_if(in_state(AppState::Ready)),
+            );
+    }
+}
+
+#[derive(Component)]
+struct ControlsPanel;
+
+/// A button rebinding an [`Action`], also put on its label.
+#[derive(Component, Clone, Copy)]
+struct ControlButton(Action);
+
+#[derive(Component)]
+struct ResetControlsButton;
+
+fn control_label(
+    action: Action,
+    bindings: &InputBindings,
+    rebinding: Option<&Rebinding>,
+) -> String {
+    if rebinding.is_some_and(|rebinding| rebinding.0 == action) {
+        return format!("{}: press an input...", action.label());
+    }
+    let bound: Vec<String> = bindings.get(action).iter().map(|b| b.label()).collect();
+    if bound.is_empty() {
+        format!("{}: -", action.label())
+    } else {
+        format!("{}: {}", action.label(), bound.join(", "))
+    }
+}
+
+fn setup(mut commands: Commands, fonts: Res<FontAssets>, bindings: Res<InputBindings>) {
+    let text_font = TextFont {
+        font: fonts.vt323_regular.clone(),
+        font_size: TextOptions::DATA_TEXT_SIZE,
+        ..default()
+    };
+    // Centered in the window, clear of the panels along its sides.
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Vw(100.0),
+                height: Val::Vh(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            Visibility::Hidden,
+            ControlsPanel,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(4.0),
+                        padding: UiRect::all(Val::Px(6.0)),
+                        ..default()
+                    },
+                    BackgroundColor(OverlayColor::BG_COLOR),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new("Controls"),
+                        text_font.clone(),
+                        TextColor(OverlayColor::YELLOW),
+                    ));
+                    parent.spawn((
+                        Text::new("Click an action, then press its new input. Escape cancels."),
+                        text_font.clone(),
+                        TextColor(OverlayColor::GREEN),
+                    ));
+                    // Two columns, as one would be taller than most windows.
+                    parent
+                        .spawn(Node {
+                            display: Display::Grid,
+                            grid_template_columns: RepeatedGridTrack::auto(2),
+                            column_gap: Val::Px(16.0),
+                            row_gap: Val::Px(2.0),
+                            ..default()
+                        })
+                        .with_children(|grid| {
+                            for action in Action::ALL {
+                                let button = ControlButton(action);
+                                grid.spawn((Button, BackgroundColor(Color::NONE), button))
+                                    .with_child((
+                                        Text::new(control_label(action, &bindings, None)),
+                                        text_font.clone(),
+                                        TextColor(OverlayColor::GREEN),
+                                        button,
+                                    ));
+                            }
+                        });
+                    parent
+                        .spawn((Button, BackgroundColor(Color::NONE), ResetControlsButton))
+                        .with_child((
+                            Text::new("Reset to defaults"),
+                            text_font.clone(),
+                            TextColor(OverlayColor::YELLOW),
+                        ));
+                });
+        });
+}
+
+// Release the cursor to click the entries of the shown controls panel.
+fn toggle_controls_panel(
+    actions: Res<ActionState>,
+    mut panel: Single<&mut Visibility, With<ControlsPanel>>,
+) {
+    if actions.just_pressed(Action::ToggleControls) {
+        panel.toggle_visible_hidden();
+    }
+}
+
+// The buttons are highlighted by the settings panel, which handles every button.
+fn press_control_buttons(
+    mut commands: Commands,
+    controls: Query<(&Interaction, &ControlButton), Changed<Interaction>>,
+    reset: Query<&Interaction, (Changed<Interaction>, With<ResetControlsButton>)>,
+    mut bindings: ResMut<InputBindings>,
+) {
+    for (interaction, button) in &controls {
+        if *interaction == Interaction::Pressed {
+            commands.insert_resource(Rebinding(button.0));
+        }
+    }
+    if reset
+        .iter()
+        .any(|interaction| *interaction == Interaction::Pressed)
+    {
+        *bindings = InputBindings::default();
+    }
+}
+
+fn update_control_labels(
+    bindings: Res<InputBindings>,
+    rebinding: Option<Res<Rebinding>>,
+    mut labels: Query<(&mut Text, &ControlButton)>,
+) {
+    for (mut text, button) in &mut labels {
+        text.0 = control_label(button.0, &bindings, rebinding.as_deref());
+    }
+}
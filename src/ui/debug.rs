@@ -4,9 +4,11 @@ use core::time::Duration;
 
 use crate::{
     AppState,
+    input::{Action, ActionState},
     loading::FontAssets,
+    overlay::{ClimateLayer, TerrainOverlay},
     ui::{OverlayColor, TextOptions},
-    voxel::TerrainWorld,
+    voxel::{RegenerateWorld, TerrainWorld},
 };
 
 #[derive(Default)]
@@ -27,6 +29,7 @@ impl Plugin for ChunkUiPlugin {
                         update_current_chunk_gizmo,
                         update_chunk_data_text,
                         update_camera_ui,
+                        update_biome_ui_text,
                         ui_toggle_actions,
                     )
                         .run_if(in_state(AppState::Ready)),
@@ -34,9 +37,11 @@ impl Plugin for ChunkUiPlugin {
                         customize_voxel_data,
                         customize_camera_data,
                         customize_chunk_data,
+                        customize_biome_data,
                         toggle_chunk_data,
                         toggle_camera_data,
                         toggle_voxel_data,
+                        toggle_biome_data,
                     )
                         .run_if(resource_changed::<DebugUiConfig>),
                 ),
@@ -63,6 +68,9 @@ struct CameraInfoText;
 #[derive(Component)]
 struct VoxelInfoText;
 
+#[derive(Component)]
+struct BiomeInfoText;
+
 #[derive(Resource, Clone)]
 struct DebugUiConfig {
     text_config: TextFont,
@@ -71,6 +79,7 @@ struct DebugUiConfig {
     show_camera_data: bool,
     show_chunk_data: bool,
     show_voxel_data: bool,
+    show_biome_data: bool,
 }
 
 impl Default for DebugUiConfig {
@@ -85,6 +94,7 @@ impl Default for DebugUiConfig {
             show_camera_data: true,
             show_chunk_data: true,
             show_voxel_data: true,
+            show_biome_data: true,
             refresh_interval: Duration::from_millis(100),
         }
     }
@@ -140,6 +150,18 @@ fn setup(mut commands: Commands, config: Res<DebugUiConfig>) {
                     config.text_config.clone(),
                     TextColor(config.text_color),
                 ));
+            parent
+                .spawn((
+                    Text::new("Biome: "),
+                    TextColor(config.text_color),
+                    config.text_config.clone(),
+                    BiomeInfoText,
+                ))
+                .with_child((
+                    TextSpan::default(),
+                    config.text_config.clone(),
+                    TextColor(config.text_color),
+                ));
         });
 }
 
@@ -203,6 +225,43 @@ fn update_camera_ui(
     }
 }
 
+// Shows what the generator computed for the column under the camera, to see why the terrain
+// there looks the way it does.
+fn update_biome_ui_text(
+    camera_query: Query<&Transform, With<VoxelWorldCamera<TerrainWorld>>>,
+    terrain: Res<TerrainWorld>,
+    query: Query<Entity, With<BiomeInfoText>>,
+    config: Res<DebugUiConfig>,
+    time: Res<Time>,
+    mut writer: TextUiWriter,
+    mut last_update: Local<Duration>,
+) {
+    let Ok(transform) = camera_query.single() else {
+        return;
+    };
+    *last_update += time.delta();
+    if *last_update < config.refresh_interval {
+        return;
+    }
+    *last_update = Duration::ZERO;
+
+    let position = transform.translation.floor().as_ivec3();
+    let column = terrain.sample_column(position.x, position.z);
+    for entity in &query {
+        *writer.text(entity, 1) = format!(
+            "{:?}, Height: {:.1}\nC: {:.3}, E: {:.3}, PV: {:.3}, T: {:.3}, H: {:.3}, W: {:.3}",
+            column.biome,
+            column.height_offset,
+            column.continent,
+            column.erosion,
+            column.peaks_valleys,
+            column.temperature,
+            column.humidity,
+            column.weirdness,
+        );
+    }
+}
+
 fn update_current_chunk_gizmo(
     camera_query: Query<&Transform, With<VoxelWorldCamera<TerrainWorld>>>,
     voxel_world: VoxelWorld<TerrainWorld>,
@@ -313,6 +372,19 @@ fn customize_camera_data(
     }
 }
 
+fn customize_biome_data(
+    config: Res<DebugUiConfig>,
+    query: Query<Entity, With<BiomeInfoText>>,
+    mut writer: TextUiWriter,
+) {
+    for entity in &query {
+        writer.for_each_font(entity, |mut font| {
+            *font = config.text_config.clone();
+        });
+        writer.for_each_color(entity, |mut color| color.0 = config.text_color);
+    }
+}
+
 fn despawn_recursive(commands: &mut Commands, entity: Entity, children: &Query<&Children>) {
     if let Ok(child_list) = children.get(entity) {
         for child in child_list.iter() {
@@ -422,18 +494,79 @@ fn toggle_voxel_data(
     }
 }
 
-// Simple keyboard actions to toggle UI elements. F1 toggles camera data, F2 toggles chunk data, F3 toggles voxel data.
-fn ui_toggle_actions(mut config: ResMut<DebugUiConfig>, keys: Res<ButtonInput<KeyCode>>) {
-    if keys.just_pressed(KeyCode::F1) {
+fn toggle_biome_data(
+    mut commands: Commands,
+    config: Res<DebugUiConfig>,
+    query: Query<Entity, With<BiomeInfoText>>,
+    root: Query<Entity, With<DebugUiRoot>>,
+    children: Query<&Children>,
+) {
+    if config.show_biome_data {
+        if query.is_empty()
+            && let Ok(root_ent) = root.single()
+        {
+            commands.entity(root_ent).with_children(|parent| {
+                parent
+                    .spawn((
+                        Text::new("Biome: "),
+                        TextColor(config.text_color),
+                        config.text_config.clone(),
+                        BiomeInfoText,
+                    ))
+                    .with_child((
+                        TextSpan::default(),
+                        config.text_config.clone(),
+                        TextColor(config.text_color),
+                    ));
+            });
+        }
+    } else {
+        for e in &query {
+            despawn_recursive(&mut commands, e, &children);
+        }
+    }
+}
+
+// Actions toggling UI elements: camera, chunk, voxel and biome data, and the biome overlay. The
+// heatmap action cycles through the climate heatmaps, and the world can be regenerated from the
+// current world params.
+fn ui_toggle_actions(
+    mut config: ResMut<DebugUiConfig>,
+    mut overlay: ResMut<TerrainOverlay>,
+    mut regenerate: MessageWriter<RegenerateWorld>,
+    actions: Res<ActionState>,
+) {
+    if actions.just_pressed(Action::ToggleCameraHud) {
         config.show_camera_data = !config.show_camera_data;
         info!("Toggled camera UI -> {}", config.show_camera_data);
     }
-    if keys.just_pressed(KeyCode::F2) {
+    if actions.just_pressed(Action::ToggleChunkHud) {
         config.show_chunk_data = !config.show_chunk_data;
         info!("Toggled chunk UI -> {}", config.show_chunk_data);
     }
-    if keys.just_pressed(KeyCode::F3) {
+    if actions.just_pressed(Action::ToggleVoxelHud) {
         config.show_voxel_data = !config.show_voxel_data;
         info!("Toggled voxel UI -> {}", config.show_voxel_data);
     }
+    if actions.just_pressed(Action::ToggleBiomeHud) {
+        config.show_biome_data = !config.show_biome_data;
+        info!("Toggled biome UI -> {}", config.show_biome_data);
+    }
+    // The heatmap binding defaults to Shift+F5, which also presses F5 of the biome overlay.
+    if actions.just_pressed(Action::CycleHeatmap) {
+        *overlay = match *overlay {
+            TerrainOverlay::Heatmap(layer) => TerrainOverlay::Heatmap(layer.next()),
+            _ => TerrainOverlay::Heatmap(ClimateLayer::Continent),
+        };
+        info!("Terrain overlay -> {:?}", *overlay);
+    } else if actions.just_pressed(Action::ToggleBiomeOverlay) {
+        *overlay = match *overlay {
+            TerrainOverlay::Off => TerrainOverlay::Biome,
+            _ => TerrainOverlay::Off,
+        };
+        info!("Terrain overlay -> {:?}", *overlay);
+    }
+    if actions.just_pressed(Action::RegenerateWorld) {
+        regenerate.write(RegenerateWorld);
+    }
 }
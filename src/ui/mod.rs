@@ -1,10 +1,18 @@
 use bevy::{app::Plugin, prelude::Color};
 
-use crate::ui::{compass::CompassPlugin, debug::ChunkUiPlugin, instrument::InstrumentPlugin};
+use crate::ui::{
+    bookmarks::BookmarksUiPlugin, compass::CompassPlugin, controls::ControlsUiPlugin,
+    debug::ChunkUiPlugin, instrument::InstrumentPlugin, settings::SettingsUiPlugin,
+    spline_editor::SplineEditorPlugin,
+};
 
+mod bookmarks;
 mod compass;
+mod controls;
 mod debug;
 mod instrument;
+mod settings;
+mod spline_editor;
 
 pub struct OverlayColor;
 
@@ -25,6 +33,14 @@ pub struct UiPlugin;
 
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut bevy::app::App) {
-        app.add_plugins((InstrumentPlugin, ChunkUiPlugin::default(), CompassPlugin));
+        app.add_plugins((
+            InstrumentPlugin,
+            ChunkUiPlugin::default(),
+            CompassPlugin,
+            SettingsUiPlugin,
+            ControlsUiPlugin,
+            BookmarksUiPlugin,
+            SplineEditorPlugin,
+        ));
     }
 }
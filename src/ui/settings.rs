@@ -0,0 +1,181 @@
+use bevy::prelude::*;
+
+use crate::{
+    AppState,
+    input::{Action, ActionState},
+    loading::FontAssets,
+    resource_pack::ResourcePack,
+    settings::Settings,
+    ui::{OverlayColor, TextOptions},
+    world_params::WorldParams,
+};
+
+pub struct SettingsUiPlugin;
+
+impl Plugin for SettingsUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::Ready), setup)
+            .add_systems(
+                Update,
+                (
+                    toggle_settings_panel,
+                    highlight_buttons,
+                    press_setting_buttons,
+                    update_setting_labels.run_if(resource_changed::<Settings>),
+                )
+                    .run_if(in_state(AppState::Ready)),
+            );
+    }
+}
+
+#[derive(Component)]
+struct SettingsPanel;
+
+/// A button changing one of the [`Settings`], also put on its label.
+#[derive(Component, Clone, Copy)]
+enum SettingButton {
+    ResourcePack,
+    AmbientOcclusion,
+    GreedyMeshing,
+    WorldPreset,
+}
+
+impl SettingButton {
+    const ALL: [SettingButton; 4] = [
+        SettingButton::ResourcePack,
+        SettingButton::AmbientOcclusion,
+        SettingButton::GreedyMeshing,
+        SettingButton::WorldPreset,
+    ];
+
+    fn label(self, settings: &Settings) -> String {
+        match self {
+            SettingButton::ResourcePack => {
+                format!("Resource pack: < {} >", settings.resource_pack)
+            }
+            SettingButton::AmbientOcclusion => {
+                format!("Ambient occlusion: {}", on_off(settings.ambient_occlusion))
+            }
+            SettingButton::GreedyMeshing => {
+                format!("Greedy meshing: {}", on_off(settings.greedy_meshing))
+            }
+            SettingButton::WorldPreset => {
+                format!("World preset: < {} >", settings.world_preset)
+            }
+        }
+    }
+
+    fn press(self, settings: &mut Settings) {
+        match self {
+            SettingButton::ResourcePack => {
+                let names: Vec<&str> = ResourcePack::names().collect();
+                let current = names
+                    .iter()
+                    .position(|name| *name == settings.resource_pack)
+                    .unwrap_or(names.len() - 1);
+                settings.resource_pack = names[(current + 1) % names.len()].to_string();
+            }
+            SettingButton::AmbientOcclusion => {
+                settings.ambient_occlusion = !settings.ambient_occlusion;
+            }
+            SettingButton::GreedyMeshing => {
+                settings.greedy_meshing = !settings.greedy_meshing;
+            }
+            SettingButton::WorldPreset => {
+                let names = WorldParams::preset_names();
+                let current = names
+                    .iter()
+                    .position(|name| *name == settings.world_preset)
+                    .unwrap_or(names.len() - 1);
+                settings.world_preset = names[(current + 1) % names.len()].clone();
+            }
+        }
+    }
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled { "on" } else { "off" }
+}
+
+fn setup(mut commands: Commands, fonts: Res<FontAssets>, settings: Res<Settings>) {
+    let text_font = TextFont {
+        font: fonts.vt323_regular.clone(),
+        font_size: TextOptions::DATA_TEXT_SIZE,
+        ..default()
+    };
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(128.0),
+                right: Val::Px(10.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(6.0)),
+                ..default()
+            },
+            BackgroundColor(OverlayColor::BG_COLOR),
+            Visibility::Hidden,
+            SettingsPanel,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("Settings (F10)"),
+                text_font.clone(),
+                TextColor(OverlayColor::YELLOW),
+            ));
+            for button in SettingButton::ALL {
+                parent
+                    .spawn((Button, BackgroundColor(Color::NONE), button))
+                    .with_child((
+                        Text::new(button.label(&settings)),
+                        text_font.clone(),
+                        TextColor(OverlayColor::GREEN),
+                        button,
+                    ));
+            }
+        });
+}
+
+// Release the cursor to click the entries of the shown settings panel.
+fn toggle_settings_panel(
+    actions: Res<ActionState>,
+    mut panel: Single<&mut Visibility, With<SettingsPanel>>,
+) {
+    if actions.just_pressed(Action::ToggleSettings) {
+        panel.toggle_visible_hidden();
+    }
+}
+
+type ButtonColors<'w, 's> = Query<
+    'w,
+    's,
+    (&'static Interaction, &'static mut BackgroundColor),
+    (Changed<Interaction>, With<Button>),
+>;
+
+fn highlight_buttons(mut buttons: ButtonColors) {
+    for (interaction, mut color) in &mut buttons {
+        color.0 = match interaction {
+            Interaction::Hovered | Interaction::Pressed => OverlayColor::BG_COLOR.lighter(0.1),
+            Interaction::None => Color::NONE,
+        };
+    }
+}
+
+fn press_setting_buttons(
+    buttons: Query<(&Interaction, &SettingButton), Changed<Interaction>>,
+    mut settings: ResMut<Settings>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction == Interaction::Pressed {
+            button.press(&mut settings);
+        }
+    }
+}
+
+fn update_setting_labels(settings: Res<Settings>, mut labels: Query<(&mut Text, &SettingButton)>) {
+    for (mut text, button) in &mut labels {
+        text.0 = button.label(&settings);
+    }
+}
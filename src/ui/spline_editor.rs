@@ -0,0 +1,422 @@
+use core::time::Duration;
+
+use bevy::prelude::*;
+use bevy_voxel_world::prelude::VoxelWorldCamera;
+
+use crate::{
+    AppState,
+    input::{Action, ActionState},
+    loading::FontAssets,
+    ui::{OverlayColor, TextOptions},
+    voxel::{ColumnHeight, RegenerateWorld, TerrainWorld},
+    world_params::{WorldParams, WorldPreset, spline},
+};
+
+const PLOT_WIDTH: f32 = 480.0;
+const PLOT_HEIGHT: f32 = 240.0;
+const KEY_SIZE: f32 = 10.0;
+const CURVE_POINTS: usize = 120;
+const CURVE_POINT_SIZE: f32 = 3.0;
+const HISTOGRAM_BINS: usize = 48;
+// Columns sampled for the histogram, in a square grid around the camera.
+const HISTOGRAM_GRID: i32 = 48;
+const HISTOGRAM_SPACING: i32 = 64;
+const HISTOGRAM_REFRESH: Duration = Duration::from_millis(500);
+
+/// Panel plotting the terrain splines of the [`WorldParams`] with draggable keys, over a
+/// histogram of the noise values sampled around the camera.
+///
+/// Edits change the [`WorldParams`] resource only, they are applied to the world by regenerating
+/// it and saving writes them to the world params file.
+pub struct SplineEditorPlugin;
+
+impl Plugin for SplineEditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SplineEditor>()
+            .add_systems(OnEnter(AppState::Ready), setup)
+            .add_systems(
+                Update,
+                (
+                    toggle_spline_editor,
+                    press_editor_buttons,
+                    spawn_key_handles,
+                    update_plot.run_if(
+                        resource_changed::<WorldParams>.or(resource_changed::<SplineEditor>),
+                    ),
+                    update_histogram,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Ready)),
+            );
+    }
+}
+
+/// The spline shown in the editor.
+#[derive(Resource, Default)]
+struct SplineEditor {
+    curve: SplineCurve,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum SplineCurve {
+    #[default]
+    Continent,
+    Erosion,
+    PeaksValleys,
+    Squashing,
+}
+
+impl SplineCurve {
+    const ALL: [SplineCurve; 4] = [
+        SplineCurve::Continent,
+        SplineCurve::Erosion,
+        SplineCurve::PeaksValleys,
+        SplineCurve::Squashing,
+    ];
+
+    fn next(self) -> Self {
+        let index = Self::ALL
+            .iter()
+            .position(|curve| *curve == self)
+            .unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn keys(self, params: &WorldParams) -> &[(f64, f64)] {
+        match self {
+            SplineCurve::Continent => &params.continent_spline,
+            SplineCurve::Erosion => &params.erosion_spline,
+            SplineCurve::PeaksValleys => &params.peaks_valleys_spline,
+            SplineCurve::Squashing => &params.squashing_spline,
+        }
+    }
+
+    fn keys_mut(self, params: &mut WorldParams) -> &mut [(f64, f64)] {
+        match self {
+            SplineCurve::Continent => &mut params.continent_spline,
+            SplineCurve::Erosion => &mut params.erosion_spline,
+            SplineCurve::PeaksValleys => &mut params.peaks_valleys_spline,
+            SplineCurve::Squashing => &mut params.squashing_spline,
+        }
+    }
+
+    // Range of outputs the plot covers, keys can't be dragged outside of it.
+    fn range(self) -> (f64, f64) {
+        match self {
+            SplineCurve::Continent => (-128.0, 128.0),
+            SplineCurve::Erosion => (-64.0, 64.0),
+            SplineCurve::PeaksValleys => (-32.0, 32.0),
+            SplineCurve::Squashing => (0.0, 1.0),
+        }
+    }
+
+    // The noise value the spline is sampled with.
+    fn noise_value(self, column: &ColumnHeight) -> f64 {
+        match self {
+            SplineCurve::Continent => column.continent,
+            SplineCurve::Erosion => column.erosion,
+            SplineCurve::PeaksValleys | SplineCurve::Squashing => column.peaks_valleys,
+        }
+    }
+
+    // Position of a point of the spline in the plot, in pixels from the bottom left corner.
+    fn plot_position(self, (x, y): (f64, f64)) -> Vec2 {
+        let (min, max) = self.range();
+        Vec2::new(
+            ((x + 1.0) / 2.0) as f32 * PLOT_WIDTH,
+            ((y - min) / (max - min)).clamp(0.0, 1.0) as f32 * PLOT_HEIGHT,
+        )
+    }
+}
+
+#[derive(Component)]
+struct SplineEditorPanel;
+
+#[derive(Component)]
+struct SplinePlot;
+
+#[derive(Component)]
+struct CurvePoint(usize);
+
+#[derive(Component)]
+struct HistogramBar(usize);
+
+/// A draggable key of the shown spline, by its index.
+#[derive(Component)]
+struct KeyHandle(usize);
+
+/// A button of the editor, also put on its label.
+#[derive(Component, Clone, Copy)]
+enum EditorButton {
+    Curve,
+    Regenerate,
+    Save,
+}
+
+impl EditorButton {
+    fn label(self, editor: &SplineEditor) -> String {
+        match self {
+            EditorButton::Curve => {
+                let (min, max) = editor.curve.range();
+                format!("Spline: < {:?} > ({min} to {max})", editor.curve)
+            }
+            EditorButton::Regenerate => "Regenerate world (F9)".to_string(),
+            EditorButton::Save => "Save to the world preset".to_string(),
+        }
+    }
+}
+
+fn setup(mut commands: Commands, fonts: Res<FontAssets>, editor: Res<SplineEditor>) {
+    let text_font = TextFont {
+        font: fonts.vt323_regular.clone(),
+        font_size: TextOptions::DATA_TEXT_SIZE,
+        ..default()
+    };
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(10.0),
+                left: Val::Px(10.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(6.0)),
+                ..default()
+            },
+            BackgroundColor(OverlayColor::BG_COLOR),
+            Visibility::Hidden,
+            SplineEditorPanel,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("Spline editor (F6)"),
+                text_font.clone(),
+                TextColor(OverlayColor::YELLOW),
+            ));
+            parent
+                .spawn((Button, BackgroundColor(Color::NONE), EditorButton::Curve))
+                .with_child((
+                    Text::new(EditorButton::Curve.label(&editor)),
+                    text_font.clone(),
+                    TextColor(OverlayColor::GREEN),
+                    EditorButton::Curve,
+                ));
+            parent
+                .spawn((
+                    Node {
+                        width: Val::Px(PLOT_WIDTH),
+                        height: Val::Px(PLOT_HEIGHT),
+                        ..default()
+                    },
+                    BackgroundColor(Color::BLACK.with_alpha(0.5)),
+                    SplinePlot,
+                ))
+                .with_children(|plot| {
+                    let bar_width = PLOT_WIDTH / HISTOGRAM_BINS as f32;
+                    for bin in 0..HISTOGRAM_BINS {
+                        plot.spawn((
+                            Node {
+                                position_type: PositionType::Absolute,
+                                left: Val::Px(bin as f32 * bar_width),
+                                bottom: Val::Px(0.0),
+                                width: Val::Px(bar_width - 1.0),
+                                height: Val::Px(0.0),
+                                ..default()
+                            },
+                            BackgroundColor(OverlayColor::YELLOW.with_alpha(0.25)),
+                            HistogramBar(bin),
+                        ));
+                    }
+                    for point in 0..CURVE_POINTS {
+                        plot.spawn((
+                            Node {
+                                position_type: PositionType::Absolute,
+                                width: Val::Px(CURVE_POINT_SIZE),
+                                height: Val::Px(CURVE_POINT_SIZE),
+                                ..default()
+                            },
+                            BackgroundColor(OverlayColor::GREEN),
+                            CurvePoint(point),
+                        ));
+                    }
+                });
+            for button in [EditorButton::Regenerate, EditorButton::Save] {
+                parent
+                    .spawn((Button, BackgroundColor(Color::NONE), button))
+                    .with_child((
+                        Text::new(button.label(&editor)),
+                        text_font.clone(),
+                        TextColor(OverlayColor::GREEN),
+                        button,
+                    ));
+            }
+        });
+}
+
+// Release the cursor to drag the keys of the shown spline editor.
+fn toggle_spline_editor(
+    actions: Res<ActionState>,
+    mut panel: Single<&mut Visibility, With<SplineEditorPanel>>,
+) {
+    if actions.just_pressed(Action::ToggleSplineEditor) {
+        panel.toggle_visible_hidden();
+    }
+}
+
+fn press_editor_buttons(
+    buttons: Query<(&Interaction, &EditorButton), Changed<Interaction>>,
+    mut editor: ResMut<SplineEditor>,
+    params: Res<WorldParams>,
+    preset: Res<WorldPreset>,
+    mut regenerate: MessageWriter<RegenerateWorld>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            EditorButton::Curve => editor.curve = editor.curve.next(),
+            EditorButton::Regenerate => {
+                regenerate.write(RegenerateWorld);
+            }
+            EditorButton::Save => params.save(&preset.0),
+        }
+    }
+}
+
+// Replaces the key handles when another spline is shown, or the spline got another number of
+// keys from a preset.
+fn spawn_key_handles(
+    mut commands: Commands,
+    editor: Res<SplineEditor>,
+    params: Res<WorldParams>,
+    plot: Single<Entity, With<SplinePlot>>,
+    handles: Query<Entity, With<KeyHandle>>,
+) {
+    let keys = editor.curve.keys(&params);
+    if !editor.is_changed() && handles.iter().len() == keys.len() {
+        return;
+    }
+    for handle in &handles {
+        commands.entity(handle).despawn();
+    }
+    for (index, key) in keys.iter().enumerate() {
+        let position = editor.curve.plot_position(*key);
+        commands
+            .spawn((
+                // Not a Button, the button highlighting would clear its color.
+                Interaction::default(),
+                Node {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(position.x - KEY_SIZE / 2.0),
+                    bottom: Val::Px(position.y - KEY_SIZE / 2.0),
+                    width: Val::Px(KEY_SIZE),
+                    height: Val::Px(KEY_SIZE),
+                    ..default()
+                },
+                BackgroundColor(OverlayColor::RED),
+                KeyHandle(index),
+                ChildOf(*plot),
+            ))
+            .observe(drag_key);
+    }
+}
+
+// Keys move between their neighbours, the first and last keys only up and down so the spline
+// keeps covering the whole noise range.
+fn drag_key(
+    drag: On<Pointer<Drag>>,
+    handles: Query<&KeyHandle>,
+    editor: Res<SplineEditor>,
+    mut params: ResMut<WorldParams>,
+) {
+    let Ok(KeyHandle(index)) = handles.get(drag.entity) else {
+        return;
+    };
+    let curve = editor.curve;
+    let (min, max) = curve.range();
+    let keys = curve.keys_mut(&mut params);
+    let last = keys.len() - 1;
+
+    let (mut x, mut y) = keys[*index];
+    if *index > 0 && *index < last {
+        let lowest = keys[*index - 1].0 + 0.001;
+        let highest = keys[*index + 1].0 - 0.001;
+        x = (x + (drag.delta.x / PLOT_WIDTH * 2.0) as f64).clamp(lowest, highest);
+    }
+    y = (y - (drag.delta.y / PLOT_HEIGHT) as f64 * (max - min)).clamp(min, max);
+    keys[*index] = (x, y);
+}
+
+fn update_plot(
+    editor: Res<SplineEditor>,
+    params: Res<WorldParams>,
+    mut handles: Query<(&mut Node, &KeyHandle), Without<CurvePoint>>,
+    mut points: Query<(&mut Node, &CurvePoint), Without<KeyHandle>>,
+    mut labels: Query<(&mut Text, &EditorButton)>,
+) {
+    let curve = editor.curve;
+    let keys = curve.keys(&params);
+    for (mut node, KeyHandle(index)) in &mut handles {
+        if let Some(key) = keys.get(*index) {
+            let position = curve.plot_position(*key);
+            node.left = Val::Px(position.x - KEY_SIZE / 2.0);
+            node.bottom = Val::Px(position.y - KEY_SIZE / 2.0);
+        }
+    }
+
+    let spline = spline(keys);
+    for (mut node, CurvePoint(point)) in &mut points {
+        let x = *point as f64 / (CURVE_POINTS - 1) as f64 * 2.0 - 1.0;
+        let y = spline.clamped_sample(x).unwrap_or(0.0);
+        let position = curve.plot_position((x, y));
+        node.left = Val::Px(position.x - CURVE_POINT_SIZE / 2.0);
+        node.bottom = Val::Px(position.y - CURVE_POINT_SIZE / 2.0);
+    }
+
+    for (mut text, button) in &mut labels {
+        text.0 = button.label(&editor);
+    }
+}
+
+// Distribution of the noise value of the shown spline around the camera, refreshed while the
+// editor is visible. Only the noise is sampled, erosion would simulate every region in the grid
+// on the main thread.
+fn update_histogram(
+    panel: Single<&Visibility, With<SplineEditorPanel>>,
+    camera: Query<&Transform, With<VoxelWorldCamera<TerrainWorld>>>,
+    terrain: Res<TerrainWorld>,
+    editor: Res<SplineEditor>,
+    time: Res<Time>,
+    mut bars: Query<(&mut Node, &HistogramBar)>,
+    mut last_update: Local<Duration>,
+) {
+    if **panel == Visibility::Hidden {
+        return;
+    }
+    *last_update += time.delta();
+    if *last_update < HISTOGRAM_REFRESH && !editor.is_changed() {
+        return;
+    }
+    *last_update = Duration::ZERO;
+    let Ok(transform) = camera.single() else {
+        return;
+    };
+
+    let center = transform.translation.floor().as_ivec3();
+    let mut bins = [0u32; HISTOGRAM_BINS];
+    for i in 0..HISTOGRAM_GRID {
+        for j in 0..HISTOGRAM_GRID {
+            let x = center.x + (i - HISTOGRAM_GRID / 2) * HISTOGRAM_SPACING;
+            let z = center.z + (j - HISTOGRAM_GRID / 2) * HISTOGRAM_SPACING;
+            let value = editor.curve.noise_value(&terrain.sample_height(x, z));
+            let bin = ((value + 1.0) / 2.0 * HISTOGRAM_BINS as f64) as usize;
+            bins[bin.min(HISTOGRAM_BINS - 1)] += 1;
+        }
+    }
+
+    let highest = bins.iter().copied().max().unwrap_or(0).max(1) as f32;
+    for (mut node, HistogramBar(bin)) in &mut bars {
+        node.height = Val::Px(bins[*bin] as f32 / highest * PLOT_HEIGHT);
+    }
+}
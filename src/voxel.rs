@@ -1,6 +1,10 @@
-use std::sync::Arc;
+use std::{iter, ops::RangeInclusive, sync::Arc};
 
-use bevy::{platform::collections::HashMap, prelude::*};
+use bevy::{
+    ecs::{schedule::ScheduleConfigs, system::ScheduleSystem},
+    platform::collections::HashMap,
+    prelude::*,
+};
 use bevy_voxel_world::{
     custom_meshing::{CHUNK_SIZE_F, CHUNK_SIZE_I, CHUNK_SIZE_U},
     prelude::*,
@@ -8,18 +12,112 @@ use bevy_voxel_world::{
 // custom_meshing::{CHUNK_SIZE_F, CHUNK_SIZE_I, CHUNK_SIZE_U, VoxelArray, generate_chunk_mesh},
 
 use noise::{HybridMulti, NoiseFn, Perlin};
-use splines::{Interpolation, Key, Spline};
+use serde::{Deserialize, Serialize};
+use splines::Spline;
+
+use crate::{
+    hydraulic::HydraulicErosion,
+    meshing::{ChunkMeshingPlugin, empty_mesh},
+    overlay::TerrainOverlayPlugin,
+    resource_pack::{
+        DEFAULT_RESOURCE_PACK, PendingResourcePack, ResourcePack, ResourcePackPlugin,
+        voxel_material,
+    },
+    settings::Settings,
+    terrain_pass::{CaveNoise, TerrainPass, TerrainPipeline, VoxelContext},
+    world_params::{ClimateParams, Generator, RiverParams, WorldParams, WorldPreset, spline},
+};
 
 pub struct VoxelPlugin;
 
 impl Plugin for VoxelPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(VoxelWorldPlugin::with_config(TerrainWorld::default()));
+        app.add_plugins(ResourcePackPlugin);
+
+        let preset = app.world().resource::<Settings>().world_preset.clone();
+        let params = WorldParams::load_preset_or_default(&preset);
+        app.init_resource::<TerrainPipeline>();
+        let pipeline = app.world().resource::<TerrainPipeline>();
+        let pending = app.world().resource::<PendingResourcePack>();
+        let mut terrain = TerrainWorld::with_pipeline(&params, pipeline);
+        terrain.use_resource_pack(&pending.pack);
+        let material = voxel_material(pending.texture.clone());
+
+        app.insert_resource(params)
+            .insert_resource(WorldPreset(preset))
+            .add_message::<RegenerateWorld>()
+            .add_plugins((
+                VoxelWorldPlugin::with_config(terrain).with_material(material),
+                ChunkMeshingPlugin,
+                TerrainOverlayPlugin,
+            ))
+            .add_systems(Update, regeneration_systems());
+    }
+}
+
+fn regeneration_systems() -> ScheduleConfigs<ScheduleSystem> {
+    (
+        select_world_preset.run_if(resource_changed::<Settings>),
+        // Also runs on the first frame, when the terrain may have been built before plugins
+        // added after this one changed the pipeline. No chunks exist yet then.
+        regenerate_with_pipeline.run_if(resource_changed::<TerrainPipeline>),
+        regenerate_world,
+    )
+        .chain()
+}
+
+/// Rebuilds the terrain generator from the current [`WorldParams`] and despawns every chunk, so
+/// the world is generated again around the camera.
+#[derive(Message, Debug, Default, Clone, Copy)]
+pub struct RegenerateWorld;
+
+pub(crate) fn regenerate_world(
+    mut commands: Commands,
+    mut messages: MessageReader<RegenerateWorld>,
+    params: Res<WorldParams>,
+    pipeline: Res<TerrainPipeline>,
+    mut terrain: ResMut<TerrainWorld>,
+    chunks: Query<Entity, With<Chunk<TerrainWorld>>>,
+) {
+    if messages.read().count() == 0 {
+        return;
+    }
+    terrain.use_params(&params, &pipeline);
+    for chunk in &chunks {
+        commands.entity(chunk).try_insert(NeedsDespawn);
     }
+    info!("Regenerating world");
+}
+
+// Passes added to or removed from the pipeline by other plugins only take effect once the
+// world is regenerated with them.
+fn regenerate_with_pipeline(mut regenerate: MessageWriter<RegenerateWorld>) {
+    regenerate.write(RegenerateWorld);
+}
+
+// Switches to the preset chosen in the settings and regenerates the world with it.
+fn select_world_preset(
+    settings: Res<Settings>,
+    mut preset: ResMut<WorldPreset>,
+    mut params: ResMut<WorldParams>,
+    mut regenerate: MessageWriter<RegenerateWorld>,
+) {
+    let requested = settings.world_preset.as_str();
+    if requested == preset.0 {
+        return;
+    }
+    let Some(loaded) = WorldParams::load_preset(requested) else {
+        warn!("Unknown world preset {requested:?}, keeping {:?}", preset.0);
+        return;
+    };
+    info!("Switching to world preset {requested:?}");
+    preset.0 = requested.to_string();
+    *params = loaded;
+    regenerate.write(RegenerateWorld);
 }
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Default)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Default, Serialize, Deserialize)]
 pub enum BlockMaterial {
     Grass,
     Dirt,
@@ -42,36 +140,100 @@ pub enum BlockMaterial {
     Platinum,
     Lava,
     Adamantine,
+    Bedrock,
+    Gravel,
 }
 
 impl BlockMaterial {
-    fn get_texture_index_map(mat: BlockMaterial) -> [u32; 3] {
-        match mat {
-            BlockMaterial::Grass => [0, 1, 2],
-            BlockMaterial::Dirt => [2, 2, 2],
-            BlockMaterial::Stone => [3, 3, 3],
-            BlockMaterial::Water => [4, 4, 4],
-            BlockMaterial::Marble => [5, 5, 5],
-            BlockMaterial::Sand => [6, 6, 6],
-            BlockMaterial::Snow => [7, 7, 7],
-            BlockMaterial::Ice => [8, 8, 8],
-            BlockMaterial::Wood => [9, 9, 9],
-            BlockMaterial::Leaves => [10, 10, 10],
-            BlockMaterial::Clay => [11, 11, 11],
-            BlockMaterial::Iron => [12, 12, 12],
-            BlockMaterial::Gold => [13, 13, 13],
-            BlockMaterial::Coal => [14, 14, 14],
-            BlockMaterial::Copper => [15, 15, 15],
-            BlockMaterial::Tin => [16, 16, 16],
-            BlockMaterial::Silver => [17, 17, 17],
-            BlockMaterial::Platinum => [18, 18, 18],
-            BlockMaterial::Lava => [19, 19, 19],
-            BlockMaterial::Adamantine => [20, 20, 20],
+    pub const ALL: [BlockMaterial; 22] = [
+        BlockMaterial::Grass,
+        BlockMaterial::Dirt,
+        BlockMaterial::Stone,
+        BlockMaterial::Water,
+        BlockMaterial::Marble,
+        BlockMaterial::Sand,
+        BlockMaterial::Snow,
+        BlockMaterial::Ice,
+        BlockMaterial::Wood,
+        BlockMaterial::Leaves,
+        BlockMaterial::Clay,
+        BlockMaterial::Iron,
+        BlockMaterial::Gold,
+        BlockMaterial::Coal,
+        BlockMaterial::Copper,
+        BlockMaterial::Tin,
+        BlockMaterial::Silver,
+        BlockMaterial::Platinum,
+        BlockMaterial::Lava,
+        BlockMaterial::Adamantine,
+        BlockMaterial::Bedrock,
+        BlockMaterial::Gravel,
+    ];
+
+    // Names of the block textures (file stems in a resource pack directory) used for the
+    // top, sides and bottom of the voxel, unless the pack maps the material to other textures.
+    pub fn texture_names(self) -> [&'static str; 3] {
+        match self {
+            BlockMaterial::Grass => ["grass_top", "grass_side", "dirt"],
+            BlockMaterial::Dirt => ["dirt"; 3],
+            BlockMaterial::Stone => ["stone"; 3],
+            BlockMaterial::Water => ["water"; 3],
+            BlockMaterial::Marble => ["marble"; 3],
+            BlockMaterial::Sand => ["sand"; 3],
+            BlockMaterial::Snow => ["snow"; 3],
+            BlockMaterial::Ice => ["ice"; 3],
+            BlockMaterial::Wood => ["wood"; 3],
+            BlockMaterial::Leaves => ["leaves"; 3],
+            BlockMaterial::Clay => ["clay"; 3],
+            BlockMaterial::Iron => ["iron"; 3],
+            BlockMaterial::Gold => ["gold"; 3],
+            BlockMaterial::Coal => ["coal"; 3],
+            BlockMaterial::Copper => ["copper"; 3],
+            BlockMaterial::Tin => ["tin"; 3],
+            BlockMaterial::Silver => ["silver"; 3],
+            BlockMaterial::Platinum => ["platinum"; 3],
+            BlockMaterial::Lava => ["lava"; 3],
+            BlockMaterial::Adamantine => ["adamantine"; 3],
+            BlockMaterial::Bedrock => ["bedrock"; 3],
+            BlockMaterial::Gravel => ["gravel"; 3],
+        }
+    }
+
+    // Translucent blocks are meshed separately from the rest of the chunk and alpha blended.
+    pub fn is_translucent(self) -> bool {
+        matches!(self, BlockMaterial::Water | BlockMaterial::Ice)
+    }
+
+    // Alpha of the vertex colors of translucent blocks.
+    pub fn opacity(self) -> f32 {
+        match self {
+            BlockMaterial::Water => 0.6,
+            BlockMaterial::Ice => 0.85,
+            _ => 1.0,
+        }
+    }
+
+    // Block light level the material gives off, up to `light::MAX_LIGHT`.
+    pub fn light_emission(self) -> u8 {
+        match self {
+            BlockMaterial::Lava => 15,
+            _ => 0,
         }
     }
 }
 
-// Biomes are determined by the climate, height and weirdness.
+/// Whether the voxel blocks light and hides the faces of the blocks behind it.
+pub fn is_opaque(voxel: &WorldVoxel<BlockMaterial>) -> bool {
+    matches!(voxel, WorldVoxel::Solid(material) if !material.is_translucent())
+}
+
+/// Height of the sea surface, the ocean fills the air below it.
+pub const SEA_LEVEL: f64 = -10.0;
+
+// How far above a river's water level its banks are sand.
+pub(crate) const RIVER_BANK_HEIGHT: f64 = 2.0;
+
+// Biomes are determined by the climate, continentalness, height and weirdness.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 pub enum Biome {
@@ -86,122 +248,235 @@ pub enum Biome {
     // Polar biomes
     Taiga,
     Tundra,
+    // Oceans, below sea level
+    Ocean,
+    DeepOcean,
+    FrozenOcean,
+    // Coasts, just above sea level
+    Beach,
+    StonyShore,
+    // High peaks
+    Mountains,
 }
 
 #[derive(Resource, Clone)]
 pub struct TerrainWorld {
-    continents: Arc<(HybridMulti<Perlin>, Spline<f64, f64>)>,
-    erosion: Arc<(HybridMulti<Perlin>, Spline<f64, f64>)>,
-    peaks_valleys: Arc<(HybridMulti<Perlin>, Spline<f64, f64>)>,
-    squashing_spline: Arc<Spline<f64, f64>>,
-    temperatures: Arc<HybridMulti<Perlin>>,
-    humidity: Arc<HybridMulti<Perlin>>,
-    weirdness: Arc<HybridMulti<Perlin>>,
-    density_a: Arc<Perlin>,
-    density_b: Arc<Perlin>,
-    density_c: Arc<Perlin>,
-    spaghetti_a: Arc<Perlin>,
-    spaghetti_b: Arc<Perlin>,
+    columns: Arc<ColumnNoise>,
+    passes: Arc<[Box<dyn TerrainPass>]>,
+    caves: Arc<CaveNoise>,
+    generator: Arc<Generator>,
+    vertical_chunks: RangeInclusive<i32>,
+    texture_indices: Arc<[[u32; 3]]>,
+}
+
+// The 2D noise and splines shaping the terrain, sampled once per x/z column.
+struct ColumnNoise {
+    continents: (HybridMulti<Perlin>, Spline<f64, f64>),
+    erosion: (HybridMulti<Perlin>, Spline<f64, f64>),
+    peaks_valleys: (HybridMulti<Perlin>, Spline<f64, f64>),
+    squashing_spline: Spline<f64, f64>,
+    temperatures: HybridMulti<Perlin>,
+    humidity: HybridMulti<Perlin>,
+    weirdness: HybridMulti<Perlin>,
+    climate: Climate,
+    rivers: Rivers,
+    hydraulic_erosion: Option<HydraulicErosion>,
+}
+
+struct Climate {
+    snow_line: Perlin,
+    params: ClimateParams,
+}
+
+struct Rivers {
+    noise: Perlin,
+    params: RiverParams,
+}
+
+// Where a river valley, or the water of a river next to it, changes the terrain of a column.
+struct RiverSample {
+    // From 0 at the edge of the valley to 1 at the banks of the channel.
+    valley: f64,
+    height: f64,
+    water_level: f64,
+}
+
+/// What the generator computed for one x/z column of the terrain.
+#[derive(Debug, Clone, Copy)]
+pub struct ColumnSample {
+    pub continent: f64,
+    pub erosion: f64,
+    pub peaks_valleys: f64,
+    /// Temperature noise, lowered by the height of the terrain above sea level.
+    pub temperature: f64,
+    pub humidity: f64,
+    pub weirdness: f64,
+    /// Surface height from the continent, erosion and peaks_valleys splines, lowered by rivers.
+    pub height_offset: f64,
+    pub squashing_factor: f64,
+    pub biome: Biome,
+    /// How far into a river valley the column is, from 0 outside to 1 at the river.
+    pub river: f64,
+    /// Water fills the air below this height: the sea level, or the level of a river.
+    pub water_level: f64,
+    /// Above the snow line, the surface is covered in snow and the water is frozen.
+    pub snow: bool,
 }
 
 impl Default for TerrainWorld {
     fn default() -> Self {
-        let mut continent_noise = HybridMulti::<Perlin>::new(1234);
-        continent_noise.octaves = 5;
-        continent_noise.frequency = 1.1;
-        continent_noise.lacunarity = 2.8;
-        continent_noise.persistence = 0.4;
-
-        let continent_spline = Spline::from_iter([
-            Key::new(-1.0, -128.0, Interpolation::Linear),
-            Key::new(-0.96, -96.0, Interpolation::Linear),
-            Key::new(-0.91, -80.0, Interpolation::Linear),
-            Key::new(-0.8, -64.0, Interpolation::Linear),
-            Key::new(-0.7, -60.0, Interpolation::Linear),
-            Key::new(-0.5, -50.0, Interpolation::Linear),
-            Key::new(-0.4, -40.0, Interpolation::Linear),
-            Key::new(-0.3, -36.0, Interpolation::Linear),
-            Key::new(-0.2, -30.0, Interpolation::Linear),
-            Key::new(-0.1, -26.0, Interpolation::Linear),
-            Key::new(0.0, -20.0, Interpolation::Linear),
-            Key::new(0.1, -16.0, Interpolation::Linear),
-            Key::new(0.2, 10.0, Interpolation::Linear),
-            Key::new(0.7, 10.0, Interpolation::Linear),
-            // High plateaus
-            Key::new(0.8, 64.0, Interpolation::Linear),
-            Key::new(0.9, 80.0, Interpolation::Linear),
-            Key::new(1.0, 96.0, Interpolation::Linear),
-        ]);
-
-        let mut erosion_noise = HybridMulti::<Perlin>::new(5678);
-        erosion_noise.octaves = 3;
-        erosion_noise.frequency = 0.5;
-        erosion_noise.lacunarity = 2.0;
-        erosion_noise.persistence = 0.3;
-
-        let erosion_spline = Spline::from_iter([
-            Key::new(-1.0, 48.0, Interpolation::Linear),
-            Key::new(0.0, 36.0, Interpolation::Linear),
-            Key::new(0.667, 6.0, Interpolation::Linear),
-            Key::new(1.0, -48.01, Interpolation::Linear),
-        ]);
-
-        let mut pv_noise = HybridMulti::<Perlin>::new(7890);
-        pv_noise.octaves = 4;
-        pv_noise.frequency = 0.3;
-        pv_noise.lacunarity = 2.0;
-        pv_noise.persistence = 0.5;
-
-        let pv_spline = Spline::from_iter([
-            // Base level for the perlin noise
-            Key::new(-1.0, 0.0, Interpolation::Linear),
-            // Peaks and valleys
-            Key::new(0.0, 10.0, Interpolation::Linear),
-            Key::new(1.0, 20.0, Interpolation::Linear),
-        ]);
-
-        let squash_factor_spline = Spline::from_iter([
-            Key::new(-1.0, 1.0, Interpolation::Linear),
-            Key::new(0.0, 0.4, Interpolation::Linear),
-            Key::new(1.0, 0.03, Interpolation::Linear),
-        ]);
-
-        let mut temperature_noise = HybridMulti::<Perlin>::new(2233);
-        temperature_noise.octaves = 1;
-        temperature_noise.frequency = 0.2;
-
-        let mut humidity_noise = HybridMulti::<Perlin>::new(4455);
-        humidity_noise.octaves = 2;
-        humidity_noise.frequency = 0.3;
-
-        let mut weirdness_noise = HybridMulti::<Perlin>::new(6677);
-        weirdness_noise.octaves = 3;
-        weirdness_noise.frequency = 0.8;
-
-        let density_a = Perlin::new(9876);
-        let density_b = Perlin::new(5432);
-        let density_c = Perlin::new(1111);
-
-        let spaghetti_a = Perlin::new(31337);
-        let spaghetti_b = Perlin::new(73313);
+        Self::new(&WorldParams::default())
+    }
+}
+
+impl TerrainWorld {
+    pub fn new(params: &WorldParams) -> Self {
+        Self::with_pipeline(params, &TerrainPipeline::default())
+    }
+
+    pub fn with_pipeline(params: &WorldParams, pipeline: &TerrainPipeline) -> Self {
+        let resource_pack = ResourcePack::load_or_default(DEFAULT_RESOURCE_PACK);
 
         Self {
-            continents: Arc::new((continent_noise, continent_spline)),
-            erosion: Arc::new((erosion_noise, erosion_spline)),
-            peaks_valleys: Arc::new((pv_noise, pv_spline)),
-            squashing_spline: Arc::new(squash_factor_spline),
-            temperatures: Arc::new(temperature_noise),
-            humidity: Arc::new(humidity_noise),
-            weirdness: Arc::new(weirdness_noise),
-            density_a: Arc::new(density_a),
-            density_b: Arc::new(density_b),
-            density_c: Arc::new(density_c),
-            spaghetti_a: Arc::new(spaghetti_a),
-            spaghetti_b: Arc::new(spaghetti_b),
+            columns: Self::column_noise(params),
+            passes: pipeline.build(params),
+            caves: Arc::new(CaveNoise::new(params)),
+            generator: Arc::new(params.generator.clone()),
+            vertical_chunks: params.min_chunk_y..=params.max_chunk_y,
+            texture_indices: resource_pack.texture_index_table().into(),
+        }
+    }
+
+    fn column_noise(params: &WorldParams) -> Arc<ColumnNoise> {
+        Arc::new(ColumnNoise {
+            continents: (params.continents.noise(), spline(&params.continent_spline)),
+            erosion: (params.erosion.noise(), spline(&params.erosion_spline)),
+            peaks_valleys: (
+                params.peaks_valleys.noise(),
+                spline(&params.peaks_valleys_spline),
+            ),
+            squashing_spline: spline(&params.squashing_spline),
+            temperatures: params.temperatures.noise(),
+            humidity: params.humidity.noise(),
+            weirdness: params.weirdness.noise(),
+            climate: Climate {
+                snow_line: Perlin::new(params.climate.snow_line_seed),
+                params: params.climate,
+            },
+            rivers: Rivers {
+                noise: Perlin::new(params.rivers.seed),
+                params: params.rivers,
+            },
+            hydraulic_erosion: params
+                .hydraulic_erosion
+                .enabled
+                .then(|| HydraulicErosion::new(params.hydraulic_erosion)),
+        })
+    }
+
+    // Only affects chunks generated from now on, see RegenerateWorld to rebuild existing ones.
+    pub fn use_params(&mut self, params: &WorldParams, pipeline: &TerrainPipeline) {
+        self.columns = Self::column_noise(params);
+        self.passes = pipeline.build(params);
+        self.caves = Arc::new(CaveNoise::new(params));
+        self.generator = Arc::new(params.generator.clone());
+        self.vertical_chunks = params.min_chunk_y..=params.max_chunk_y;
+    }
+
+    // Only affects chunks meshed from now on, existing chunks keep their texture indices.
+    pub fn use_resource_pack(&mut self, pack: &ResourcePack) {
+        self.texture_indices = pack.texture_index_table().into();
+    }
+
+    /// The noise values, height and biome the generator uses for the column at `x`, `z`.
+    pub fn sample_column(&self, x: i32, z: i32) -> ColumnSample {
+        sample_column(&self.columns, &self.passes, x, z)
+    }
+
+    /// The spline noise values of the column at `x`, `z` and the height they add up to, before
+    /// erosion, rivers and the terrain passes. Much cheaper than [`Self::sample_column`].
+    pub fn sample_height(&self, x: i32, z: i32) -> ColumnHeight {
+        self.columns.height(x as f64, z as f64)
+    }
+
+    /// The top block of the nearest dry column around `center` that isn't opened up by a cave,
+    /// preferring columns in one of the `biomes`. Columns are searched every `SPAWN_SEARCH_STEP`
+    /// blocks up to `SPAWN_SEARCH_RADIUS` away, without generating any chunk.
+    pub fn find_spawn(&self, center: IVec2, biomes: &[Biome]) -> Option<IVec3> {
+        match &*self.generator {
+            Generator::Noise => {}
+            Generator::Superflat(layers) => {
+                return (!layers.is_empty()).then_some(IVec3::new(center.x, -1, center.y));
+            }
+            Generator::CavesOnly => return None,
+        }
+
+        let mut fallback = None;
+        for ring in 0..=SPAWN_SEARCH_RADIUS / SPAWN_SEARCH_STEP {
+            for offset in square_ring(ring) {
+                let column = center + offset * SPAWN_SEARCH_STEP;
+                let Some((ground, biome)) = self.dry_ground(column.x, column.y) else {
+                    continue;
+                };
+                if biomes.contains(&biome) {
+                    return Some(ground);
+                }
+                fallback.get_or_insert(ground);
+            }
         }
+        fallback
+    }
+
+    // The top block of a column of the noise generator, if it is above water, and no cave
+    // carves the column from the sky down to it.
+    fn dry_ground(&self, x: i32, z: i32) -> Option<(IVec3, Biome)> {
+        let column = self.sample_column(x, z);
+        let top = (*self.vertical_chunks.end() + 1) * CHUNK_SIZE_I - 1;
+        let bottom = *self.vertical_chunks.start() * CHUNK_SIZE_I;
+        // The density noise is at most 1, so the ground can't reach higher than this.
+        let highest = column.height_offset + 1.0 / column.squashing_factor.max(0.001);
+        let start = (highest.ceil() as i32).min(top);
+
+        for y in (bottom..=start).rev() {
+            let pos = IVec3::new(x, y, z);
+            let mut context = VoxelContext {
+                pos,
+                column: &column,
+                density: 0.0,
+                caves: None,
+            };
+            let voxel = self.passes.iter().fold(WorldVoxel::Air, |voxel, pass| {
+                pass.voxel(&mut context, voxel)
+            });
+            if context.caves.is_some() {
+                return None;
+            }
+            match voxel {
+                WorldVoxel::Air | WorldVoxel::Unset => continue,
+                WorldVoxel::Solid(
+                    BlockMaterial::Water | BlockMaterial::Ice | BlockMaterial::Lava,
+                ) => return None,
+                WorldVoxel::Solid(_) if (y as f64) < column.water_level => return None,
+                WorldVoxel::Solid(_) => return Some((pos, column.biome)),
+            }
+        }
+        None
     }
 }
 
+// How far and how finely TerrainWorld::find_spawn searches, in blocks.
+const SPAWN_SEARCH_RADIUS: i32 = 1024;
+const SPAWN_SEARCH_STEP: i32 = 8;
+
+// The offsets on the edge of a square `ring` steps away from its center.
+fn square_ring(ring: i32) -> impl Iterator<Item = IVec2> {
+    (-ring..=ring).flat_map(move |x| {
+        (-ring..=ring)
+            .filter(move |&z| x.abs() == ring || z.abs() == ring)
+            .map(move |z| IVec2::new(x, z))
+    })
+}
+
 impl VoxelWorldConfig for TerrainWorld {
     type MaterialIndex = BlockMaterial;
     type ChunkUserBundle = ();
@@ -215,63 +490,52 @@ impl VoxelWorldConfig for TerrainWorld {
     }
 
     fn voxel_lookup_delegate(&self) -> VoxelLookupDelegate<Self::MaterialIndex> {
-        let continents = Arc::clone(&self.continents);
-        let erosion = Arc::clone(&self.erosion);
-        let peaks_valleys = Arc::clone(&self.peaks_valleys);
-        let squashing_spline = Arc::clone(&self.squashing_spline);
-        let temperatures = Arc::clone(&self.temperatures);
-        let humidity = Arc::clone(&self.humidity);
-        let weirdness = Arc::clone(&self.weirdness);
-        let density_a = Arc::clone(&self.density_a);
-        let density_b = Arc::clone(&self.density_b);
-        let density_c = Arc::clone(&self.density_c);
-        let spaghetti_a = Arc::clone(&self.spaghetti_a);
-        let spaghetti_b = Arc::clone(&self.spaghetti_b);
+        let columns = Arc::clone(&self.columns);
+        let passes = Arc::clone(&self.passes);
+        let caves = Arc::clone(&self.caves);
+        let generator = Arc::clone(&self.generator);
+        let vertical_chunks = self.vertical_chunks.clone();
         Box::new(move |chunk_pos, lod_level, _previous| {
-            if chunk_pos.y < -8 {
-                return Box::new(|_, _| WorldVoxel::Solid(BlockMaterial::Lava)); // Lava will be our bedrock for now. TODO: Fluid stuff to make molten and sea level less uniform
+            if chunk_pos.y < *vertical_chunks.start() {
+                return Box::new(|_, _| WorldVoxel::Solid(BlockMaterial::Bedrock));
             }
-            if chunk_pos.y > 8 {
+            if chunk_pos.y > *vertical_chunks.end() {
                 return Box::new(|_, _| WorldVoxel::Air);
             }
 
-            let continents = Arc::clone(&continents);
-            let erosion = Arc::clone(&erosion);
-            let peaks_valleys = Arc::clone(&peaks_valleys);
-            let squashing_spline = Arc::clone(&squashing_spline);
-            let temperatures = Arc::clone(&temperatures);
-            let humidity = Arc::clone(&humidity);
-            let weirdness = Arc::clone(&weirdness);
-            let density_a = Arc::clone(&density_a);
-            let density_b = Arc::clone(&density_b);
-            let density_c = Arc::clone(&density_c);
-            let spaghetti_a = Arc::clone(&spaghetti_a);
-            let spaghetti_b = Arc::clone(&spaghetti_b);
-            get_voxel_fn(
-                continents,
-                erosion,
-                peaks_valleys,
-                squashing_spline,
-                temperatures,
-                humidity,
-                weirdness,
-                density_a,
-                density_b,
-                density_c,
-                spaghetti_a,
-                spaghetti_b,
-                chunk_pos,
-                lod_level,
-            )
+            match &*generator {
+                Generator::Noise => get_voxel_fn(
+                    Arc::clone(&columns),
+                    Arc::clone(&passes),
+                    chunk_pos,
+                    lod_level,
+                ),
+                Generator::Superflat(layers) => superflat_voxel_fn(layers),
+                Generator::CavesOnly => caves_only_voxel_fn(Arc::clone(&caves)),
+            }
         })
     }
 
-    fn texture_index_mapper(&self) -> Arc<dyn Fn(Self::MaterialIndex) -> [u32; 3] + Send + Sync> {
-        Arc::new(BlockMaterial::get_texture_index_map)
+    // Chunks are lit and meshed by ChunkMeshingPlugin once their voxels are in the chunk map.
+    // This hook can't build them: it only sees the chunk's own voxels, while its light depends
+    // on the neighbouring chunks, and the library caches its meshes by voxel hash. Two chunks
+    // with the same voxels but different light would share one mesh, and a chunk relit because
+    // its neighbour changed keeps its voxels, so remeshing it hits the cache and never calls
+    // the hook again. The library still needs a mesh to finish a chunk, so every chunk gets a
+    // clone of the same empty one.
+    fn chunk_meshing_delegate(
+        &self,
+    ) -> ChunkMeshingDelegate<Self::MaterialIndex, Self::ChunkUserBundle> {
+        let empty = Arc::new(empty_mesh());
+        Some(Box::new(move |_, _, _, _, _| {
+            let empty = Arc::clone(&empty);
+            Box::new(move |_, _, _, _| (Mesh::clone(&empty), None))
+        }))
     }
 
-    fn voxel_texture(&self) -> Option<(String, u32)> {
-        Some(("textures/voxel_atlas.png".into(), 21))
+    fn texture_index_mapper(&self) -> Arc<dyn Fn(Self::MaterialIndex) -> [u32; 3] + Send + Sync> {
+        let texture_indices = Arc::clone(&self.texture_indices);
+        Arc::new(move |mat| texture_indices[mat as usize])
     }
 
     fn chunk_data_shape(&self, lod_level: LodLevel) -> UVec3 {
@@ -314,36 +578,313 @@ impl VoxelWorldConfig for TerrainWorld {
     }
 }
 
-type ColumnData = (f64, f64, f64, f64, f64);
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 struct ColumnIndex(i32, i32);
 
+impl ColumnNoise {
+    fn sample(&self, x: i32, z: i32) -> ColumnSample {
+        let (pos_x_64, pos_z_64) = (x as f64, z as f64);
+
+        let ColumnHeight {
+            continent: continent_val,
+            erosion: erosion_val,
+            peaks_valleys: pv_val,
+            base_height,
+            mut height_sample,
+        } = self.height(pos_x_64, pos_z_64);
+        if let Some(hydraulic_erosion) = &self.hydraulic_erosion {
+            height_sample += hydraulic_erosion
+                .height_change(pos_x_64, pos_z_64, |x, z| self.height(x, z).height_sample);
+        }
+
+        let mut s_factor = self.squashing_spline.clamped_sample(pv_val).unwrap_or(0.3);
+
+        let temp_val = self.climate.temperature(
+            self.temperatures
+                .get([pos_x_64 * 0.0006667, pos_z_64 * 0.0006667]),
+            height_sample,
+        );
+        let humidity_val = self
+            .humidity
+            .get([pos_x_64 * 0.0006667, pos_z_64 * 0.0006667]);
+        let weirdness_val = self.weirdness.get([pos_x_64 * 0.00033, pos_z_64 * 0.00033]);
+        // Biomes follow the terrain around rivers, not the bottom of their valleys.
+        let biome = select_biome(
+            temp_val,
+            humidity_val,
+            weirdness_val,
+            continent_val,
+            erosion_val,
+            height_sample,
+        );
+        let snow = self.climate.is_snowy(pos_x_64, pos_z_64, temp_val);
+
+        let mut river = 0.0;
+        let mut water_level = SEA_LEVEL;
+        if let Some(sample) =
+            self.rivers
+                .sample(pos_x_64, pos_z_64, height_sample, base_height, |x, z| {
+                    self.height(x, z).base_height
+                })
+        {
+            river = sample.valley;
+            water_level = sample.water_level;
+            height_sample = sample.height;
+            // Flatten the density noise in the valley so the water stays between its banks.
+            s_factor += (1.0 - s_factor) * sample.valley;
+        }
+
+        ColumnSample {
+            continent: continent_val,
+            erosion: erosion_val,
+            peaks_valleys: pv_val,
+            temperature: temp_val,
+            humidity: humidity_val,
+            weirdness: weirdness_val,
+            height_offset: height_sample,
+            squashing_factor: s_factor,
+            biome,
+            river,
+            water_level,
+            snow,
+        }
+    }
+}
+
+impl Climate {
+    // Colder the higher the terrain is above the sea, the sea floor is as warm as the sea.
+    fn temperature(&self, temperature: f64, height: f64) -> f64 {
+        temperature - (height - SEA_LEVEL).max(0.0) * self.params.lapse_rate
+    }
+
+    fn is_snowy(&self, x: f64, z: f64, temperature: f64) -> bool {
+        let ClimateParams {
+            snow_temperature,
+            snow_line_scale,
+            snow_line_variation,
+            ..
+        } = self.params;
+        let variation = self
+            .snow_line
+            .get([x * snow_line_scale, z * snow_line_scale]);
+        temperature < snow_temperature + variation * snow_line_variation
+    }
+}
+
+/// The noise values of the splines shaping the terrain height, and the height they add up to.
+pub struct ColumnHeight {
+    pub continent: f64,
+    pub erosion: f64,
+    pub peaks_valleys: f64,
+    /// Height from the continent and erosion splines only, smooth at a large scale.
+    pub base_height: f64,
+    pub height_sample: f64,
+}
+
+impl ColumnNoise {
+    fn height(&self, pos_x_64: f64, pos_z_64: f64) -> ColumnHeight {
+        let continent_val = self
+            .continents
+            .0
+            .get([pos_x_64 * 0.00025, pos_z_64 * 0.00025]);
+        let mut height_sample = self
+            .continents
+            .1
+            .clamped_sample(continent_val)
+            .unwrap_or(0.0);
+
+        let erosion_val = self.erosion.0.get([pos_x_64 * 0.0025, pos_z_64 * 0.0025]);
+        height_sample += self.erosion.1.clamped_sample(erosion_val).unwrap_or(0.0);
+        let base_height = height_sample;
+
+        let pv_val = self.peaks_valleys.0.get([pos_x_64 * 0.01, pos_z_64 * 0.01]);
+        height_sample += self.peaks_valleys.1.clamped_sample(pv_val).unwrap_or(0.0);
+
+        ColumnHeight {
+            continent: continent_val,
+            erosion: erosion_val,
+            peaks_valleys: pv_val,
+            base_height,
+            height_sample,
+        }
+    }
+}
+
+impl Rivers {
+    // Where a river valley or a river next to it changes a column, given its height, the
+    // continent and erosion height at any column, and its own.
+    fn sample(
+        &self,
+        x: f64,
+        z: f64,
+        height: f64,
+        base_height: f64,
+        base_height_at: impl Fn(f64, f64) -> f64,
+    ) -> Option<RiverSample> {
+        let RiverParams {
+            enabled,
+            channel_width,
+            valley_width,
+            depth,
+            ..
+        } = self.params;
+        if !enabled {
+            return None;
+        }
+        // Water held by the channel next to this column, if it is higher than this column's. The
+        // column has to be solid up to it, or that water would hang over it.
+        let held = |level: f64| {
+            [(1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)]
+                .into_iter()
+                .map(|(dx, dz)| (x + dx, z + dz))
+                .filter(|&(x, z)| self.distance(x, z) < channel_width)
+                .map(|(x, z)| self.water_level(base_height_at(x, z)))
+                .filter(|&neighbour| neighbour > level)
+                .reduce(f64::max)
+        };
+
+        let distance = self.distance(x, z);
+        if distance >= valley_width {
+            return held(SEA_LEVEL).map(|level| RiverSample {
+                valley: 0.0,
+                height: height.max(level),
+                water_level: SEA_LEVEL,
+            });
+        }
+
+        let water_level = self.water_level(base_height);
+        let t = ((valley_width - distance) / (valley_width - channel_width).max(f64::EPSILON))
+            .clamp(0.0, 1.0);
+        let valley = t * t * (3.0 - 2.0 * t);
+        let channel = (1.0 - distance / channel_width.max(f64::EPSILON)).max(0.0);
+        let bed = water_level - depth * channel;
+        let mut floor = held(water_level).unwrap_or(f64::MIN);
+        // The banks are raised to the water where the valley crosses lower terrain, so the
+        // water only fills the channel. At the sea, the water around is just as high.
+        if distance >= channel_width && water_level > SEA_LEVEL {
+            floor = floor.max(water_level);
+        }
+        Some(RiverSample {
+            valley,
+            height: (height + (bed - height) * valley).max(floor),
+            water_level,
+        })
+    }
+
+    // How far a column is from the middle of the nearest river, in river noise units.
+    fn distance(&self, x: f64, z: f64) -> f64 {
+        let scale = self.params.scale;
+        self.noise.get([x * scale, z * scale]).abs()
+    }
+
+    // The level of a river follows the continent and erosion height, which is smooth at a large
+    // scale, down to the sea.
+    fn water_level(&self, base_height: f64) -> f64 {
+        (base_height - self.params.sink).max(SEA_LEVEL).floor()
+    }
+}
+
+fn select_biome(
+    temp_val: f64,
+    humidity_val: f64,
+    weirdness_val: f64,
+    continent_val: f64,
+    erosion_val: f64,
+    height_offset: f64,
+) -> Biome {
+    if height_offset < SEA_LEVEL {
+        // Under water
+        if temp_val < -0.5 {
+            Biome::FrozenOcean
+        } else if continent_val < -0.7 || height_offset < SEA_LEVEL - 70.0 {
+            Biome::DeepOcean
+        } else {
+            Biome::Ocean
+        }
+    } else if height_offset < SEA_LEVEL + 4.0 && continent_val < 0.7 {
+        // Coasts, rocky where the terrain is rugged
+        if erosion_val < -0.3 {
+            Biome::StonyShore
+        } else {
+            Biome::Beach
+        }
+    } else if height_offset > 120.0 {
+        Biome::Mountains
+    } else if temp_val > 0.4 {
+        // Hot Climate
+        if humidity_val < -0.3 {
+            if height_offset > 50.0 {
+                Biome::Desert
+            } else {
+                Biome::Savanna
+            }
+        } else {
+            Biome::ScrubDesert
+        }
+    } else if temp_val < -0.4 {
+        // Cold Climate
+        if humidity_val < -0.3 {
+            if height_offset < 50.0 {
+                Biome::Tundra
+            } else {
+                Biome::Taiga
+            }
+        } else {
+            if height_offset < 50.0 {
+                Biome::Taiga
+            } else {
+                Biome::PineForest
+            }
+        }
+    } else {
+        // Temperate Climate (the transition zone)
+        if humidity_val < -0.3 {
+            if height_offset < 50.0 {
+                Biome::Grassland
+            } else {
+                Biome::ScrubDesert
+            }
+        } else if humidity_val > 0.2 {
+            if weirdness_val > 0.0 {
+                Biome::PineForest
+            } else {
+                Biome::Forest
+            }
+        } else if weirdness_val > 0.0 {
+            Biome::Forest
+        } else {
+            Biome::Grassland
+        }
+    }
+}
+
+// The column sample of the noise, adjusted by the passes.
+fn sample_column(
+    columns: &ColumnNoise,
+    passes: &[Box<dyn TerrainPass>],
+    x: i32,
+    z: i32,
+) -> ColumnSample {
+    let mut column = columns.sample(x, z);
+    for pass in passes {
+        pass.column(x, z, &mut column);
+    }
+    column
+}
+
 fn get_voxel_fn(
-    continents: Arc<(HybridMulti<Perlin>, Spline<f64, f64>)>,
-    erosion: Arc<(HybridMulti<Perlin>, Spline<f64, f64>)>,
-    peaks_valleys: Arc<(HybridMulti<Perlin>, Spline<f64, f64>)>,
-    squashing_spline: Arc<Spline<f64, f64>>,
-    temperatures: Arc<HybridMulti<Perlin>>,
-    humidity: Arc<HybridMulti<Perlin>>,
-    weirdness: Arc<HybridMulti<Perlin>>,
-    density_a: Arc<Perlin>,
-    density_b: Arc<Perlin>,
-    density_c: Arc<Perlin>,
-    spaghetti_a: Arc<Perlin>,
-    spaghetti_b: Arc<Perlin>,
+    columns: Arc<ColumnNoise>,
+    passes: Arc<[Box<dyn TerrainPass>]>,
     chunk_pos: IVec3,
     lod_level: u8,
-) -> Box<
-    dyn FnMut(IVec3, Option<WorldVoxel<BlockMaterial>>) -> WorldVoxel<BlockMaterial> + Send + Sync,
-> {
+) -> VoxelLookupFn<BlockMaterial> {
     let chunk_min = chunk_pos * CHUNK_SIZE_I;
     let chunk_max = chunk_min + IVec3::splat(CHUNK_SIZE_I);
     let skirt_enabled = lod_level > 1;
 
     // We use this to cache the noise and biome values for each y column so we only need
     // to calculate it once per x/z coordinate
-    let mut column_data_cache = HashMap::<ColumnIndex, ColumnData>::new();
+    let mut column_data_cache = HashMap::<ColumnIndex, ColumnSample>::new();
 
     // Then we return this boxed closure that captures the noise and the cache
     // This will get sent off to a separate thread for meshing by bevy_voxel_world
@@ -360,226 +901,130 @@ fn get_voxel_fn(
             }
         }
 
-        let (pos_x_64, pos_y_64, pos_z_64) = (pos.x as f64, pos.y as f64, pos.z as f64);
-        let index = ColumnIndex(pos.x, pos.z);
-        // Pass 1: Base terrain
-        let (height_offset, squashing_factor, temp_val, humidity_val, weirdness_val) =
-            match column_data_cache.get(&index) {
-                Some(data) => *data,
-                None => {
-                    let continent_val = continents.0.get([pos_x_64 * 0.00025, pos_z_64 * 0.00025]);
-                    let mut height_sample =
-                        continents.1.clamped_sample(continent_val).unwrap_or(0.0);
-
-                    let erosion_val = erosion.0.get([pos_x_64 * 0.0025, pos_z_64 * 0.0025]);
-                    height_sample += erosion.1.clamped_sample(erosion_val).unwrap_or(0.0);
-
-                    let pv_val = peaks_valleys.0.get([pos_x_64 * 0.01, pos_z_64 * 0.01]);
-                    height_sample += peaks_valleys.1.clamped_sample(pv_val).unwrap_or(0.0);
-
-                    let s_factor = squashing_spline.clamped_sample(pv_val).unwrap_or(0.3);
-
-                    let temp_val = temperatures.get([pos_x_64 * 0.0006667, pos_z_64 * 0.0006667]);
-                    let humidity_val = humidity.get([pos_x_64 * 0.0006667, pos_z_64 * 0.0006667]);
-                    let weirdness_val = weirdness.get([pos_x_64 * 0.00033, pos_z_64 * 0.00033]);
-
-                    let data = (
-                        height_sample,
-                        s_factor,
-                        temp_val,
-                        humidity_val,
-                        weirdness_val,
-                    );
-                    column_data_cache.insert(index, data);
-                    data
-                }
-            };
-        let base_density = density_a.get([pos_x_64 * 0.01, pos_y_64 * 0.01, pos_z_64 * 0.01]);
-
-        let height_gradient = (pos_y_64 - height_offset) * squashing_factor;
-
-        let final_density = base_density - height_gradient;
-        let biome = if temp_val > 0.4 {
-            // Hot Climate
-            if humidity_val < -0.3 {
-                if height_offset > 50.0 {
-                    Biome::Desert
-                } else {
-                    Biome::Savanna
-                }
-            } else {
-                Biome::ScrubDesert
-            }
-        } else if temp_val < -0.4 {
-            // Cold Climate
-            if humidity_val < -0.3 {
-                if height_offset < 50.0 {
-                    Biome::Tundra
-                } else {
-                    Biome::Taiga
-                }
-            } else {
-                if height_offset < 50.0 {
-                    Biome::Taiga
-                } else {
-                    Biome::PineForest
-                }
-            }
-        } else {
-            // Temperate Climate (the transition zone)
-            if humidity_val < -0.3 {
-                if height_offset < 50.0 {
-                    Biome::Grassland
-                } else {
-                    Biome::ScrubDesert
-                }
-            } else if humidity_val > 0.2 {
-                if weirdness_val > 0.0 {
-                    Biome::PineForest
-                } else {
-                    Biome::Forest
-                }
-            } else if weirdness_val > 0.0 {
-                Biome::Forest
-            } else {
-                Biome::Grassland
-            }
+        let column = *column_data_cache
+            .entry(ColumnIndex(pos.x, pos.z))
+            .or_insert_with(|| sample_column(&columns, &passes, pos.x, pos.z));
+        let mut context = VoxelContext {
+            pos,
+            column: &column,
+            density: 0.0,
+            caves: None,
         };
+        passes.iter().fold(WorldVoxel::Air, |voxel, pass| {
+            pass.voxel(&mut context, voxel)
+        })
+    })
+}
 
-        let mut voxel = if final_density > 0.0 {
-            let density_above =
-                base_density - ((pos_y_64 + 1.0) - height_offset) * squashing_factor;
-
-            if density_above <= 0.0 {
-                WorldVoxel::Solid(match biome {
-                    Biome::Grassland => BlockMaterial::Grass,
-                    Biome::Forest => BlockMaterial::Leaves,
-                    Biome::PineForest => BlockMaterial::Coal,
-                    Biome::Taiga => BlockMaterial::Wood,
-                    Biome::Desert => BlockMaterial::Sand,
-                    Biome::Savanna => BlockMaterial::Gold,
-                    Biome::ScrubDesert => BlockMaterial::Platinum,
-                    Biome::Tundra => BlockMaterial::Snow,
-                })
-            } else {
-                let depth_probe =
-                    base_density - ((pos_y_64 + 5.0) - height_offset) * squashing_factor;
-                if depth_probe <= 0.0 {
-                    match biome {
-                        Biome::Desert => WorldVoxel::Solid(BlockMaterial::Sand),
-                        Biome::Taiga | Biome::Tundra => WorldVoxel::Solid(BlockMaterial::Snow),
-                        _ => WorldVoxel::Solid(BlockMaterial::Dirt),
-                    }
-                } else {
-                    WorldVoxel::Solid(BlockMaterial::Stone)
-                }
-            }
-        } else if pos.y < -10 {
-            match biome {
-                Biome::Tundra => {
-                    if pos.y == -11 {
-                        WorldVoxel::Solid(BlockMaterial::Ice)
-                    } else {
-                        WorldVoxel::Solid(BlockMaterial::Water)
-                    }
-                }
-                _ => WorldVoxel::Solid(BlockMaterial::Water),
-            }
+// The layers are listed from the top down, with the top of the first one at y 0.
+fn superflat_voxel_fn(layers: &[(BlockMaterial, u32)]) -> VoxelLookupFn<BlockMaterial> {
+    let column: Vec<BlockMaterial> = layers
+        .iter()
+        .flat_map(|&(material, thickness)| iter::repeat_n(material, thickness as usize))
+        .collect();
+    Box::new(move |pos: IVec3, _previous| {
+        let depth = -1 - pos.y;
+        usize::try_from(depth)
+            .ok()
+            .and_then(|depth| column.get(depth))
+            .map_or(WorldVoxel::Air, |material| WorldVoxel::Solid(*material))
+    })
+}
+
+// Every voxel a cave would carve out is stone and everything else air, wherever the terrain is.
+fn caves_only_voxel_fn(caves: Arc<CaveNoise>) -> VoxelLookupFn<BlockMaterial> {
+    Box::new(move |pos: IVec3, _previous| {
+        if caves.carving(pos).any() {
+            WorldVoxel::Solid(BlockMaterial::Stone)
         } else {
             WorldVoxel::Air
-        };
+        }
+    })
+}
 
-        if voxel == WorldVoxel::Solid(BlockMaterial::Water)
-            || voxel == WorldVoxel::Solid(BlockMaterial::Ice)
-            || voxel == WorldVoxel::Air
-        {
-            return voxel;
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_params::DEFAULT_PRESET;
 
-        // As above, returning early to leave Water, Ice and Air blocks unchanged by cave generation,
-        // we will also protect the subsurface blocks under sea level. At least until we implement more fluid stuff
-        if pos.y < -10
-            && (voxel == WorldVoxel::Solid(BlockMaterial::Sand)
-                || voxel == WorldVoxel::Solid(BlockMaterial::Snow)
-                || voxel == WorldVoxel::Solid(BlockMaterial::Dirt))
-        {
-            return voxel;
+    struct GoldPass;
+
+    impl TerrainPass for GoldPass {
+        fn voxel(
+            &self,
+            _context: &mut VoxelContext,
+            _voxel: WorldVoxel<BlockMaterial>,
+        ) -> WorldVoxel<BlockMaterial> {
+            WorldVoxel::Solid(BlockMaterial::Gold)
         }
+    }
 
-        let cave_density = density_b.get([
-            pos_x_64 * 0.030303030303,
-            pos_y_64 * 0.030303030303,
-            pos_z_64 * 0.030303030303,
-        ]);
-        let cave_warp = density_c.get([
-            pos_x_64 * 0.030303030303,
-            pos_y_64 * 0.030303030303,
-            pos_z_64 * 0.030303030303,
-        ]);
-
-        let spaghetti_a_val = spaghetti_a
-            .get([pos_x_64 * 0.0025, pos_y_64 * 0.0025, pos_z_64 * 0.0025])
-            .abs();
-        let spaghetti_b_val = spaghetti_b
-            .get([pos_x_64 * 0.0025, pos_y_64 * 0.0025, pos_z_64 * 0.0025])
-            .abs();
-
-        let spaghetti_threshold = 0.007654321;
-        let meatball_threshold = -0.494321;
-        let cheese_threshold = 0.9813;
-        let should_carve_cheese = cave_density > cheese_threshold;
-
-        let should_carve_meatballs = cave_warp + spaghetti_a_val < meatball_threshold
-            && cave_warp + spaghetti_b_val < meatball_threshold;
-
-        let should_carve_spaghetti =
-            spaghetti_a_val < spaghetti_threshold && spaghetti_b_val < spaghetti_threshold;
-
-        // Pass 2: Carve out air for cheese and spaghetti.
-        if pos_y_64 <= height_offset + 1.
-            && (should_carve_cheese || should_carve_meatballs || should_carve_spaghetti)
-        {
-            if should_carve_cheese && !should_carve_meatballs && !should_carve_spaghetti {
-                match temp_val {
-                    t if t < -0.5 => match humidity_val {
-                        h if h < -0.5 => voxel = WorldVoxel::Solid(BlockMaterial::Copper),
-                        h if h < -0.1 => voxel = WorldVoxel::Solid(BlockMaterial::Adamantine),
-                        h if h < 0.1 => voxel = WorldVoxel::Solid(BlockMaterial::Iron),
-                        h if h < 0.5 => voxel = WorldVoxel::Solid(BlockMaterial::Marble),
-                        _ => voxel = WorldVoxel::Air,
-                    },
-                    t if t < 0.5 => match humidity_val {
-                        h if h < -0.5 => match weirdness_val {
-                            w if w < -0.5 => voxel = WorldVoxel::Solid(BlockMaterial::Coal),
-                            w if w < -0.1 => voxel = WorldVoxel::Solid(BlockMaterial::Wood),
-                            w if w < 0.1 => voxel = WorldVoxel::Solid(BlockMaterial::Gold),
-                            w if w < 0.5 => voxel = WorldVoxel::Solid(BlockMaterial::Tin),
-                            _ => voxel = WorldVoxel::Air,
-                        },
-                        h if h < -0.1 => match weirdness_val {
-                            w if w < -0.5 => voxel = WorldVoxel::Solid(BlockMaterial::Copper),
-                            w if w < 0.5 => voxel = WorldVoxel::Solid(BlockMaterial::Iron),
-                            _ => voxel = WorldVoxel::Air,
-                        },
-                        h if h < 0.1 => match weirdness_val {
-                            w if w < -0.5 => voxel = WorldVoxel::Solid(BlockMaterial::Clay),
-                            w if w < 0.5 => voxel = WorldVoxel::Solid(BlockMaterial::Dirt),
-                            _ => voxel = WorldVoxel::Air,
-                        },
-                        h if h < 0.5 => match weirdness_val {
-                            w if w < -0.5 => voxel = WorldVoxel::Solid(BlockMaterial::Silver),
-                            w if w < 0.5 => voxel = WorldVoxel::Solid(BlockMaterial::Gold),
-                            _ => voxel = WorldVoxel::Air,
-                        },
-                        _ => voxel = WorldVoxel::Air,
-                    },
-                    _ => voxel = WorldVoxel::Air,
+    #[test]
+    fn pass_pushed_after_the_plugin_changes_the_terrain() {
+        let mut app = App::new();
+        let params = WorldParams::default();
+        let pipeline = TerrainPipeline::default();
+        app.insert_resource(TerrainWorld::with_pipeline(&params, &pipeline))
+            .insert_resource(pipeline)
+            .insert_resource(params)
+            .insert_resource(Settings::default())
+            .insert_resource(WorldPreset(DEFAULT_PRESET.to_string()))
+            .add_message::<RegenerateWorld>()
+            .add_systems(Update, regeneration_systems());
+        // Like a plugin added after VoxelPlugin, before the first frame.
+        app.world_mut()
+            .resource_mut::<TerrainPipeline>()
+            .push("gold", |_| Box::new(GoldPass));
+        app.update();
+
+        let terrain = app.world().resource::<TerrainWorld>();
+        let mut voxel_at = terrain.voxel_lookup_delegate()(IVec3::ZERO, 1, None);
+        assert_eq!(
+            voxel_at(IVec3::new(3, 5, 7), None),
+            WorldVoxel::Solid(BlockMaterial::Gold)
+        );
+    }
+
+    #[test]
+    fn river_water_never_hangs_over_dry_columns() {
+        let terrain = TerrainWorld::default();
+        // A stretch of land crossed by rivers above the sea in the default world.
+        let (min, size) = (IVec2::new(-1024, -512), 512);
+        let columns: Vec<ColumnSample> = (0..size * size)
+            .map(|i| terrain.sample_column(min.x + i % size, min.y + i / size))
+            .collect();
+        let column = |x: i32, z: i32| &columns[(x + z * size) as usize];
+
+        let mut river_water = 0;
+        for z in 0..size {
+            for x in 0..size {
+                let wet = column(x, z);
+                if wet.height_offset >= wet.water_level {
+                    continue;
+                }
+                if wet.water_level > SEA_LEVEL {
+                    river_water += 1;
+                }
+                for (nx, nz) in [(x + 1, z), (x - 1, z), (x, z + 1), (x, z - 1)] {
+                    if nx < 0 || nz < 0 || nx >= size || nz >= size {
+                        continue;
+                    }
+                    let next = column(nx, nz);
+                    assert!(
+                        next.water_level >= wet.water_level
+                            || next.height_offset >= wet.water_level,
+                        "Water at level {} over ({}, {}) hangs over the ground at {} of ({}, {}), with water at {}",
+                        wet.water_level,
+                        min.x + x,
+                        min.y + z,
+                        next.height_offset,
+                        min.x + nx,
+                        min.y + nz,
+                        next.water_level,
+                    );
                 }
-            } else {
-                voxel = WorldVoxel::Air;
             }
         }
-
-        voxel
-    })
+        assert!(river_water > 0, "There should be rivers above the sea");
+    }
 }
@@ -0,0 +1,505 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use noise::{HybridMulti, Perlin};
+use serde::{Deserialize, Serialize};
+use splines::{Interpolation, Key, Spline};
+
+use crate::voxel::BlockMaterial;
+
+/// File of the default preset, where the spline editor saves its curves while it is active.
+pub const WORLD_PARAMS_PATH: &str = "assets/world_params.ron";
+
+pub const DEFAULT_PRESET: &str = "default";
+
+// Other presets are read from `<name>.ron` files in this directory, which can also override the
+// built in ones.
+const PRESETS_DIR: &str = "assets/presets";
+
+const BUILT_IN_PRESETS: [&str; 5] = [
+    DEFAULT_PRESET,
+    "amplified",
+    "islands",
+    "superflat",
+    "caves_only",
+];
+
+/// Seed, octaves, frequency, lacunarity and persistence of a fractal noise.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct NoiseParams(pub u32, pub usize, pub f64, pub f64, pub f64);
+
+impl NoiseParams {
+    fn with_defaults(seed: u32, octaves: usize, frequency: f64) -> Self {
+        Self(
+            seed,
+            octaves,
+            frequency,
+            HybridMulti::<Perlin>::DEFAULT_LACUNARITY,
+            HybridMulti::<Perlin>::DEFAULT_PERSISTENCE,
+        )
+    }
+
+    pub fn noise(&self) -> HybridMulti<Perlin> {
+        let NoiseParams(seed, octaves, frequency, lacunarity, persistence) = *self;
+        let mut noise = HybridMulti::<Perlin>::new(seed);
+        // The noise only has sources for its default number of octaves.
+        noise.octaves = octaves.clamp(1, HybridMulti::<Perlin>::DEFAULT_OCTAVES);
+        noise.frequency = frequency;
+        noise.lacunarity = lacunarity;
+        noise.persistence = persistence;
+        noise
+    }
+}
+
+/// How the voxels of the chunks between the vertical bounds of the world are decided.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum Generator {
+    /// Terrain shaped by the noise and splines, with biomes, water and caves.
+    #[default]
+    Noise,
+    /// Flat layers of blocks with their thickness, listed from the surface at y 0 down.
+    Superflat(Vec<(BlockMaterial, u32)>),
+    /// Only the caves the noise generator would carve, filled with stone to see their shape.
+    CavesOnly,
+}
+
+/// Rivers follow the zero crossings of their own noise. Around them the terrain is lowered into a
+/// valley, down to a water level that follows the continent and erosion height to the sea.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct RiverParams {
+    pub enabled: bool,
+    pub seed: u32,
+    /// Frequency of the river noise per block.
+    pub scale: f64,
+    /// Half width of the water channel, in river noise units.
+    pub channel_width: f64,
+    /// Half width of the valley around the channel, in river noise units.
+    pub valley_width: f64,
+    /// Depth of the middle of the channel below the water level.
+    pub depth: f64,
+    /// How far the water level is below the continent and erosion height.
+    pub sink: f64,
+}
+
+impl Default for RiverParams {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            seed: 2468,
+            scale: 0.0015,
+            channel_width: 0.025,
+            valley_width: 0.08,
+            depth: 4.0,
+            sink: 6.0,
+        }
+    }
+}
+
+/// Where and how much one kind of cave carves the terrain.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct CaveParams {
+    pub enabled: bool,
+    /// Frequency of the cave noise per block.
+    pub frequency: f64,
+    /// Noise value where the cave starts carving, see [`CaveSettings`] for each kind of cave.
+    pub threshold: f64,
+    /// Lowest height the cave carves at.
+    pub min_y: i32,
+    /// Highest height the cave carves at.
+    pub max_y: i32,
+}
+
+impl CaveParams {
+    fn new(frequency: f64, threshold: f64) -> Self {
+        Self {
+            frequency,
+            threshold,
+            ..default()
+        }
+    }
+}
+
+impl Default for CaveParams {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            frequency: 0.03,
+            threshold: 0.0,
+            min_y: -1024,
+            max_y: 1024,
+        }
+    }
+}
+
+/// The kinds of caves carved below the surface of the noise generator.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct CaveSettings {
+    /// Large caverns where the density noise is above the threshold. Parts not crossed by other
+    /// caves are filled with ore instead of air, depending on the climate.
+    pub cheese: CaveParams,
+    /// Chambers where the warp noise plus either spaghetti noise is below the threshold.
+    pub meatballs: CaveParams,
+    /// Long tunnels where both spaghetti noises are closer to zero than the threshold.
+    pub spaghetti: CaveParams,
+    /// Narrow winding tunnels, like spaghetti caves from their own noise at a higher frequency.
+    pub noodles: CaveParams,
+    /// Lets caves carve through the ground below seas and rivers. Caves below the water level
+    /// of a flooded column fill with water.
+    pub breach_sea_floor: bool,
+}
+
+impl Default for CaveSettings {
+    fn default() -> Self {
+        Self {
+            cheese: CaveParams::new(0.030303030303, 0.9813),
+            meatballs: CaveParams::new(0.030303030303, -0.494321),
+            spaghetti: CaveParams::new(0.0025, 0.007654321),
+            noodles: CaveParams {
+                min_y: -128,
+                max_y: 32,
+                ..CaveParams::new(0.012, 0.015)
+            },
+            breach_sea_floor: false,
+        }
+    }
+}
+
+/// How the temperature of the climate noise falls with the height of the terrain, and where
+/// that makes it cold enough for snow.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct ClimateParams {
+    /// Temperature drop per block above sea level.
+    pub lapse_rate: f64,
+    /// Surfaces colder than this are covered in snow and their water freezes.
+    pub snow_temperature: f64,
+    pub snow_line_seed: u32,
+    /// Frequency of the noise making the snow line uneven, per block.
+    pub snow_line_scale: f64,
+    /// How much the noise moves the snow temperature up or down.
+    pub snow_line_variation: f64,
+}
+
+impl Default for ClimateParams {
+    fn default() -> Self {
+        Self {
+            lapse_rate: 0.0065,
+            snow_temperature: -0.6,
+            snow_line_seed: 3579,
+            snow_line_scale: 0.02,
+            snow_line_variation: 0.1,
+        }
+    }
+}
+
+/// The bottom of the noise generator's world: a band of bedrock, with lava lakes in the deepest
+/// caves.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct UndergroundParams {
+    /// Height of the bedrock band above the bottom of the world, its upper layers are ragged.
+    pub bedrock_thickness: u32,
+    pub lava_lakes: bool,
+    /// Caves are filled with lava below this height, and with air above it.
+    pub lava_level: i32,
+}
+
+impl Default for UndergroundParams {
+    fn default() -> Self {
+        Self {
+            bedrock_thickness: 5,
+            lava_lakes: true,
+            lava_level: -224,
+        }
+    }
+}
+
+/// Droplet erosion of the terrain height, simulated on heightfields of square regions. Droplets
+/// cut ridges and gullies into slopes and leave their sediment where they slow down.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct HydraulicErosionParams {
+    pub enabled: bool,
+    pub seed: u32,
+    /// Width of a region in blocks.
+    pub region_size: u32,
+    /// Distance between the points of the heightfield in blocks.
+    pub cell_size: u32,
+    /// Number of droplets simulated per heightfield point.
+    pub droplets_per_point: f64,
+    pub max_steps: u32,
+    /// How much of its direction a droplet keeps instead of following the slope, from 0 to 1.
+    pub inertia: f64,
+    /// Sediment a droplet can carry per unit of slope, speed and water.
+    pub capacity: f64,
+    /// Slope used for the capacity on flat ground, so droplets keep eroding a little there.
+    pub min_slope: f64,
+    /// Fraction of the missing capacity taken from the ground per step.
+    pub erosion: f64,
+    /// Fraction of the excess sediment dropped per step.
+    pub deposition: f64,
+    /// Fraction of water lost per step.
+    pub evaporation: f64,
+    pub gravity: f64,
+}
+
+impl Default for HydraulicErosionParams {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            seed: 1357,
+            region_size: 256,
+            cell_size: 4,
+            droplets_per_point: 0.7,
+            max_steps: 48,
+            inertia: 0.05,
+            capacity: 4.0,
+            min_slope: 0.01,
+            erosion: 0.3,
+            deposition: 0.3,
+            evaporation: 0.02,
+            gravity: 4.0,
+        }
+    }
+}
+
+/// Everything the terrain generator is built from: the noise of each layer, the splines turning
+/// noise values into terrain height, and the seeds of the 3D density and cave noise.
+///
+/// Splines are lists of `(noise value, output)` keys with linear interpolation between them.
+/// A set of these is a world preset, see [`WorldParams::load_preset`].
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct WorldParams {
+    pub generator: Generator,
+    /// Chunks below this chunk height are bedrock.
+    pub min_chunk_y: i32,
+    /// Chunks above this chunk height are air.
+    pub max_chunk_y: i32,
+    pub continents: NoiseParams,
+    pub erosion: NoiseParams,
+    pub peaks_valleys: NoiseParams,
+    pub temperatures: NoiseParams,
+    pub humidity: NoiseParams,
+    pub weirdness: NoiseParams,
+    pub climate: ClimateParams,
+    pub density_seed_a: u32,
+    pub density_seed_b: u32,
+    pub density_seed_c: u32,
+    pub spaghetti_seed_a: u32,
+    pub spaghetti_seed_b: u32,
+    pub noodle_seed_a: u32,
+    pub noodle_seed_b: u32,
+    pub caves: CaveSettings,
+    pub underground: UndergroundParams,
+    pub rivers: RiverParams,
+    pub hydraulic_erosion: HydraulicErosionParams,
+    /// Height from the continent noise.
+    pub continent_spline: Vec<(f64, f64)>,
+    /// Height added from the erosion noise.
+    pub erosion_spline: Vec<(f64, f64)>,
+    /// Height added from the peaks and valleys noise.
+    pub peaks_valleys_spline: Vec<(f64, f64)>,
+    /// How steeply density falls off above the surface, from the peaks and valleys noise.
+    pub squashing_spline: Vec<(f64, f64)>,
+}
+
+impl Default for WorldParams {
+    fn default() -> Self {
+        Self {
+            generator: Generator::Noise,
+            min_chunk_y: -8,
+            max_chunk_y: 8,
+            continents: NoiseParams(1234, 5, 1.1, 2.8, 0.4),
+            erosion: NoiseParams(5678, 3, 0.5, 2.0, 0.3),
+            peaks_valleys: NoiseParams(7890, 4, 0.3, 2.0, 0.5),
+            temperatures: NoiseParams::with_defaults(2233, 1, 0.2),
+            humidity: NoiseParams::with_defaults(4455, 2, 0.3),
+            weirdness: NoiseParams::with_defaults(6677, 3, 0.8),
+            climate: ClimateParams::default(),
+            density_seed_a: 9876,
+            density_seed_b: 5432,
+            density_seed_c: 1111,
+            spaghetti_seed_a: 31337,
+            spaghetti_seed_b: 73313,
+            noodle_seed_a: 24680,
+            noodle_seed_b: 86420,
+            caves: CaveSettings::default(),
+            underground: UndergroundParams::default(),
+            rivers: RiverParams::default(),
+            hydraulic_erosion: HydraulicErosionParams::default(),
+            continent_spline: vec![
+                (-1.0, -128.0),
+                (-0.96, -96.0),
+                (-0.91, -80.0),
+                (-0.8, -64.0),
+                (-0.7, -60.0),
+                (-0.5, -50.0),
+                (-0.4, -40.0),
+                (-0.3, -36.0),
+                (-0.2, -30.0),
+                (-0.1, -26.0),
+                (0.0, -20.0),
+                (0.1, -16.0),
+                (0.2, 10.0),
+                (0.7, 10.0),
+                // High plateaus
+                (0.8, 64.0),
+                (0.9, 80.0),
+                (1.0, 96.0),
+            ],
+            erosion_spline: vec![(-1.0, 48.0), (0.0, 36.0), (0.667, 6.0), (1.0, -48.01)],
+            // Base level for the perlin noise, then peaks and valleys
+            peaks_valleys_spline: vec![(-1.0, 0.0), (0.0, 10.0), (1.0, 20.0)],
+            squashing_spline: vec![(-1.0, 1.0), (0.0, 0.4), (1.0, 0.03)],
+        }
+    }
+}
+
+/// The currently used preset, by name.
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct WorldPreset(pub String);
+
+impl WorldParams {
+    /// Names of the built in presets and of the preset files, sorted.
+    pub fn preset_names() -> Vec<String> {
+        let mut names: Vec<String> = BUILT_IN_PRESETS
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        if let Ok(entries) = fs::read_dir(PRESETS_DIR) {
+            names.extend(entries.flatten().filter_map(|entry| {
+                let path = entry.path();
+                let is_ron = path.extension().is_some_and(|extension| extension == "ron");
+                is_ron
+                    .then(|| path.file_stem()?.to_str().map(str::to_string))
+                    .flatten()
+            }));
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Parameters of the named preset, from its file if there is one, else the built in preset.
+    pub fn load_preset(name: &str) -> Option<Self> {
+        let path = preset_path(name);
+        match fs::read_to_string(&path) {
+            Ok(contents) => match ron::from_str(&contents) {
+                Ok(params) => return Some(params),
+                Err(e) => warn!("Ignoring invalid {}: {e}", path.display()),
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => warn!("Could not read {}: {e}", path.display()),
+        }
+        Self::built_in(name)
+    }
+
+    pub fn load_preset_or_default(name: &str) -> Self {
+        Self::load_preset(name).unwrap_or_else(|| {
+            warn!("Unknown world preset {name:?}, using {DEFAULT_PRESET:?}");
+            Self::load_preset(DEFAULT_PRESET).unwrap_or_default()
+        })
+    }
+
+    /// Writes the parameters to the file of the named preset.
+    pub fn save(&self, preset: &str) {
+        let path = preset_path(preset);
+        let config = ron::ser::PrettyConfig::default().struct_names(true);
+        let contents = match ron::ser::to_string_pretty(self, config) {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!("Could not serialize world params: {e}");
+                return;
+            }
+        };
+        if let Some(dir) = path.parent()
+            && let Err(e) = fs::create_dir_all(dir)
+        {
+            warn!("Could not create {}: {e}", dir.display());
+            return;
+        }
+        match fs::write(&path, contents) {
+            Ok(()) => info!("Saved world preset {preset:?} to {}", path.display()),
+            Err(e) => warn!("Could not write {}: {e}", path.display()),
+        }
+    }
+
+    fn built_in(name: &str) -> Option<Self> {
+        let default = WorldParams::default();
+        let params = match name {
+            DEFAULT_PRESET => default,
+            // Twice the height and a lot less squashing, so the density noise forms overhangs
+            // and spires far above the default bounds.
+            "amplified" => WorldParams {
+                max_chunk_y: 16,
+                hydraulic_erosion: HydraulicErosionParams {
+                    enabled: true,
+                    ..default.hydraulic_erosion
+                },
+                continent_spline: default
+                    .continent_spline
+                    .iter()
+                    .map(|&(x, y)| (x, y * 2.0))
+                    .collect(),
+                peaks_valleys_spline: vec![(-1.0, 0.0), (0.0, 24.0), (1.0, 64.0)],
+                squashing_spline: vec![(-1.0, 0.4), (0.0, 0.1), (1.0, 0.01)],
+                ..default
+            },
+            // Most of the continent noise range is below sea level, with land only on its peaks.
+            "islands" => WorldParams {
+                continent_spline: vec![
+                    (-1.0, -128.0),
+                    (-0.5, -96.0),
+                    (0.0, -72.0),
+                    (0.3, -56.0),
+                    (0.45, -44.0),
+                    (0.55, -30.0),
+                    (0.7, -20.0),
+                    (1.0, 16.0),
+                ],
+                ..default
+            },
+            // A chunk of ground with bedrock right below it.
+            "superflat" => WorldParams {
+                generator: Generator::Superflat(vec![
+                    (BlockMaterial::Grass, 1),
+                    (BlockMaterial::Dirt, 3),
+                    (BlockMaterial::Stone, 28),
+                ]),
+                min_chunk_y: -1,
+                max_chunk_y: 0,
+                ..default
+            },
+            "caves_only" => WorldParams {
+                generator: Generator::CavesOnly,
+                ..default
+            },
+            _ => return None,
+        };
+        Some(params)
+    }
+}
+
+fn preset_path(name: &str) -> PathBuf {
+    if name == DEFAULT_PRESET {
+        PathBuf::from(WORLD_PARAMS_PATH)
+    } else {
+        Path::new(PRESETS_DIR).join(format!("{name}.ron"))
+    }
+}
+
+/// A linearly interpolated spline through `(x, y)` keys.
+pub fn spline(keys: &[(f64, f64)]) -> Spline<f64, f64> {
+    Spline::from_iter(
+        keys.iter()
+            .map(|&(x, y)| Key::new(x, y, Interpolation::Linear)),
+    )
+}